@@ -0,0 +1,118 @@
+struct Config {
+    width: u32,
+    height: u32,
+    states: u32,
+    neighbourhood: u32,
+    colours: u32,
+    decays: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> config: Config;
+
+// The states the rule's kernel just wrote
+@group(0)
+@binding(2)
+var<storage, read> output_buffer: array<u32>;
+
+@group(0)
+@binding(3)
+var texture: texture_storage_2d<rgba32float, write>;
+
+// For each cell, how many generations it has been alive, and how many since
+// it last died, with 0xffffffff for cells that have never lived
+@group(0)
+@binding(4)
+var<storage, read_write> ages: array<vec2<u32>>;
+
+const NEVER: u32 = 0xffffffffu;
+
+fn from_xy(x: u32, y: u32) -> u32 {
+    return y * config.width + x;
+}
+
+fn is_alive(state: u32) -> bool {
+    // Dying cells of Generations rules have stopped ageing
+    return state == u32(1) || (state != u32(0) && config.decays == u32(0));
+}
+
+// Writes the cell to the texture the renderer draws, as (state, states, age,
+// generations since death), with -1 for cells that have never lived
+fn draw(position: vec3<u32>, state: u32, age: vec2<u32>) {
+    var since_death = -1.0;
+    if age.y != NEVER {
+        since_death = f32(age.y);
+    }
+    textureStore(
+        texture,
+        vec2<u32>(position.x, position.y),
+        vec4<f32>(
+          f32(state),
+          f32(config.states),
+          f32(age.x),
+          since_death
+        )
+    );
+}
+
+// Runs after every step, whatever the rule
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) position: vec3<u32>) {
+    if position.x >= config.width || position.y >= config.height {
+        return;
+    }
+    let index = from_xy(position.x, position.y);
+    let state = output_buffer[index];
+    var age = ages[index];
+
+    if is_alive(state) {
+        age = vec2<u32>(min(age.x + u32(1), NEVER - u32(1)), u32(0));
+    } else if age.x != u32(0) {
+        age = vec2<u32>(u32(0), u32(1));
+    } else if age.y != NEVER {
+        age = vec2<u32>(u32(0), min(age.y + u32(1), NEVER - u32(1)));
+    }
+    ages[index] = age;
+    draw(position, state, age);
+}
+
+// After an edit, which takes no time, so cells it brought to life start at 1,
+// cells it killed have just died, and every other cell keeps its age
+@compute
+@workgroup_size(8, 8)
+fn refresh(@builtin(global_invocation_id) position: vec3<u32>) {
+    if position.x >= config.width || position.y >= config.height {
+        return;
+    }
+    let index = from_xy(position.x, position.y);
+    let state = output_buffer[index];
+    var age = ages[index];
+
+    if is_alive(state) && age.x == u32(0) {
+        age = vec2<u32>(u32(1), u32(0));
+    } else if !is_alive(state) && age.x != u32(0) {
+        age = vec2<u32>(u32(0), u32(1));
+    }
+    ages[index] = age;
+    draw(position, state, age);
+}
+
+// After going to another board, as on undo or seeking, whose history isn't
+// known, so live cells start at 1 and dead ones have never lived
+@compute
+@workgroup_size(8, 8)
+fn reset(@builtin(global_invocation_id) position: vec3<u32>) {
+    if position.x >= config.width || position.y >= config.height {
+        return;
+    }
+    let index = from_xy(position.x, position.y);
+    let state = output_buffer[index];
+    var age = vec2<u32>(u32(0), NEVER);
+    if is_alive(state) {
+        age = vec2<u32>(u32(1), u32(0));
+    }
+    ages[index] = age;
+    draw(position, state, age);
+}
@@ -0,0 +1,38 @@
+@group(0)
+@binding(0)
+var source: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var destination: texture_storage_2d<rgba8unorm, write>;
+
+// Averages blocks of one level of the colour mip chain into the next, so a
+// pixel covering many cells shows their mean colour. Where the source has an
+// odd size, the last texel takes in the leftover row or column.
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) position: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(destination));
+    let texel = vec2<i32>(position.xy);
+    if texel.x >= size.x || texel.y >= size.y {
+        return;
+    }
+    let source_size = vec2<i32>(textureDimensions(source, 0));
+    let start = texel * 2;
+    var end = min(start + 2, source_size);
+    if texel.x == size.x - 1 {
+        end.x = source_size.x;
+    }
+    if texel.y == size.y - 1 {
+        end.y = source_size.y;
+    }
+
+    var total = vec4<f32>(0.0);
+    for (var y = start.y; y < end.y; y++) {
+        for (var x = start.x; x < end.x; x++) {
+            total += textureLoad(source, vec2<i32>(x, y), 0);
+        }
+    }
+    let count = f32((end.x - start.x) * (end.y - start.y));
+    textureStore(destination, texel, total / count);
+}
@@ -0,0 +1,92 @@
+struct Config {
+    width: u32,
+    height: u32,
+    states: u32,
+    neighbourhood: u32,
+    colours: u32,
+    decays: u32,
+}
+
+struct Rule {
+    // 0 for a @TABLE, 1 for a @TREE
+    tree: u32,
+    inputs: u32,
+    words: u32,
+    root: u32,
+    outputs: u32,
+    // Where each input is read from, relative to the cell
+    offsets: array<vec4<i32>, 9>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> config: Config;
+
+@group(0)
+@binding(1)
+var<storage, read> input_buffer: array<u32>;
+
+@group(0)
+@binding(2)
+var<storage, write> output_buffer: array<u32>;
+
+@group(0)
+@binding(4)
+var<uniform> rule: Rule;
+
+// A table's transition bitmasks, indexed by input, state and word, followed by
+// its outputs; or a tree's nodes
+@group(0)
+@binding(5)
+var<storage, read> lookup: array<u32>;
+
+fn from_xy(x: u32, y: u32) -> u32 {
+    return y * config.width + x;
+}
+
+// Cells beyond the edges are in state 0
+fn get_at(position: vec3<u32>, offset: vec2<i32>) -> u32 {
+    let x = i32(position.x) + offset.x;
+    let y = i32(position.y) + offset.y;
+    if x < 0 || y < 0 || x >= i32(config.width) || y >= i32(config.height) {
+        return u32(0);
+    }
+    return input_buffer[from_xy(u32(x), u32(y))];
+}
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) position: vec3<u32>) {
+    if position.x >= config.width || position.y >= config.height {
+        return;
+    }
+    let index = from_xy(position.x, position.y);
+
+    var cells: array<u32, 9>;
+    for (var input = u32(0); input < rule.inputs; input++) {
+        cells[input] = get_at(position, rule.offsets[input].xy);
+    }
+
+    // Cells that no transition matches stay as they are
+    var new_value = input_buffer[index];
+    if rule.tree == u32(1) {
+        var node = rule.root;
+        for (var input = u32(0); input < rule.inputs; input++) {
+            node = lookup[node + cells[input]];
+        }
+        new_value = node;
+    } else {
+        for (var word = u32(0); word < rule.words; word++) {
+            var matches = ~u32(0);
+            for (var input = u32(0); input < rule.inputs; input++) {
+                matches &= lookup[(input * config.states + cells[input]) * rule.words + word];
+            }
+            if matches != u32(0) {
+                new_value = lookup[rule.outputs + word * u32(32) + firstTrailingBit(matches)];
+                break;
+            }
+        }
+    }
+
+    output_buffer[index] = new_value;
+}
@@ -1,6 +1,14 @@
 struct Config {
     width: u32,
-    height: u32
+    height: u32,
+    states: u32,
+    // Bits of the 3x3 block in the neighbourhood, as for the table
+    neighbourhood: u32,
+    // For Immigration and QuadLife, the number of colours, where every state
+    // but 0 is a live cell of one colour
+    colours: u32,
+    // For Generations rules, where states from 2 up are dying cells, 1
+    decays: u32,
 }
 
 @group(0)
@@ -15,9 +23,11 @@ var<storage, read> input_buffer: array<u32>;
 @binding(2)
 var<storage, write> output_buffer: array<u32>;
 
+// Whether a cell is alive next generation, indexed by its 3x3 neighbourhood
+// in reading order, with the cell itself at bit 4
 @group(0)
-@binding(3)
-var texture: texture_storage_2d<rgba32float, write>;
+@binding(4)
+var<storage, read> table: array<u32, 512>;
 
 fn from_xy(x: u32, y: u32) -> u32 {
     return y * config.width + x;
@@ -36,8 +46,8 @@ fn modulus(a: i32, b: i32) -> i32 {
 // }
 
 fn get_at(position: vec3<u32>, x_mod: i32, y_mod: i32) -> u32 {
-    let x = i32(position.x) - x_mod;
-    let y = i32(position.y) - y_mod;
+    let x = i32(position.x) + x_mod;
+    let y = i32(position.y) + y_mod;
     if x == -1 {
         return u32(0);
     } else if x == i32(config.width) {
@@ -52,42 +62,88 @@ fn get_at(position: vec3<u32>, x_mod: i32, y_mod: i32) -> u32 {
     }
 }
 
+// The colour most of a newborn cell's parents have, or in QuadLife the one
+// colour none of them have when there's a tie
+fn birth_colour(parents: vec4<u32>) -> u32 {
+    var counts = parents;
+    var colour = u32(0);
+    var tied = false;
+    var missing = u32(0);
+    var absent = u32(0);
+    for (var c = u32(0); c < config.colours; c++) {
+        if counts[c] > counts[colour] {
+            colour = c;
+            tied = false;
+        } else if c != colour && counts[c] == counts[colour] {
+            tied = true;
+        }
+        if counts[c] == u32(0) {
+            missing = c;
+            absent++;
+        }
+    }
+    if tied && config.colours == u32(4) && absent == u32(1) {
+        return missing + u32(1);
+    }
+    return colour + u32(1);
+}
+
+// WORKGROUP_SIZE is filled in from life.rs
 @compute
-@workgroup_size(5, 5)
+@workgroup_size(WORKGROUP_SIZE)
 fn main(@builtin(global_invocation_id) position: vec3<u32>) {
+    if position.x >= config.width || position.y >= config.height {
+        return;
+    }
 
     let index = from_xy(position.x, position.y);
     let old_value = input_buffer[index];
 
-    var total: u32;
-    total += get_at(position, -1, -1);
-    total += get_at(position, 0, -1);
-    total += get_at(position, 1, -1);
-    total += get_at(position, -1, 0);
-    total += get_at(position, 1, 0);
-    total += get_at(position, -1, 1);
-    total += get_at(position, 0, 1);
-    total += get_at(position, 1, 1);
-
-    var alive_rules = array(0, 0, 1, 1, 0, 0, 0, 0, 0);
-    var dead_rules = array(0, 0, 0, 1, 0, 0, 0, 0, 0);
+    var neighbourhood = u32(0);
+    var bit = u32(0);
+    // How many live neighbours have each colour
+    var parents = vec4<u32>(u32(0));
+    for (var y_mod = -1; y_mod <= 1; y_mod++) {
+        for (var x_mod = -1; x_mod <= 1; x_mod++) {
+            if ((config.neighbourhood >> bit) & u32(1)) == u32(1) {
+                let state = get_at(position, x_mod, y_mod);
+                // Only fully alive cells count, not ones decaying in
+                // Generations rules
+                if config.colours != u32(0) && state != u32(0) {
+                    neighbourhood |= u32(1) << bit;
+                    if bit != u32(4) {
+                        parents[state - u32(1)] += u32(1);
+                    }
+                } else if state == u32(1) {
+                    neighbourhood |= u32(1) << bit;
+                }
+            }
+            bit++;
+        }
+    }
+    let next_alive = table[neighbourhood];
 
-    var is_alive: u32;
-    if old_value == u32(1) {
-        is_alive = u32(alive_rules[total]);
+    var new_value: u32;
+    if config.colours != u32(0) {
+        // Survivors keep their colour
+        if next_alive == u32(0) {
+            new_value = u32(0);
+        } else if old_value == u32(0) {
+            new_value = birth_colour(parents);
+        } else {
+            new_value = old_value;
+        }
+    } else if old_value == u32(0) {
+        new_value = next_alive;
+    } else if old_value == u32(1) {
+        if next_alive == u32(1) {
+            new_value = u32(1);
+        } else {
+            new_value = u32(2) % config.states;
+        }
     } else {
-        is_alive = u32(dead_rules[total]);    
+        new_value = (old_value + u32(1)) % config.states;
     }
 
-    output_buffer[index] = is_alive;
-    textureStore(
-        texture,
-        vec2<u32>(position.x, position.y),
-        vec4<f32>(
-          f32(is_alive),
-          f32(is_alive),
-          f32(is_alive),
-          1.0
-        )
-    ); 
+    output_buffer[index] = new_value;
 }
@@ -0,0 +1,160 @@
+struct Config {
+    width: u32,
+    height: u32,
+    states: u32,
+    neighbourhood: u32,
+    colours: u32,
+    decays: u32,
+}
+
+struct Rule {
+    radius: u32,
+    middle: u32,
+    survival_min: u32,
+    survival_max: u32,
+    birth_min: u32,
+    birth_max: u32,
+    // 0 for Moore, 1 for von Neumann, 2 for circular
+    shape: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> config: Config;
+
+@group(0)
+@binding(1)
+var<storage, read> input_buffer: array<u32>;
+
+@group(0)
+@binding(2)
+var<storage, write> output_buffer: array<u32>;
+
+@group(0)
+@binding(4)
+var<uniform> rule: Rule;
+
+// A summed area table: the number of live cells above and to the left of
+// each corner between cells, with width + 1 entries per row and height + 1
+// rows, so that any rectangle can be counted from its four corners
+@group(0)
+@binding(5)
+var<storage, read_write> totals: array<u32>;
+
+fn from_xy(x: u32, y: u32) -> u32 {
+    return y * config.width + x;
+}
+
+fn total_at(x: u32, y: u32) -> u32 {
+    return totals[y * (config.width + u32(1)) + x];
+}
+
+// Live cells in the rectangle from (left, top) up to but not including
+// (right, bottom)
+fn count(left: u32, top: u32, right: u32, bottom: u32) -> u32 {
+    return total_at(right, bottom) - total_at(left, bottom) - total_at(right, top)
+        + total_at(left, top);
+}
+
+// The table is built along each row first...
+@compute
+@workgroup_size(64)
+fn row_sums(@builtin(global_invocation_id) position: vec3<u32>) {
+    let y = position.x;
+    if y >= config.height {
+        return;
+    }
+    let row = (y + u32(1)) * (config.width + u32(1));
+    var total = u32(0);
+    totals[row] = total;
+    for (var x = u32(0); x < config.width; x++) {
+        total += u32(input_buffer[from_xy(x, y)] == u32(1));
+        totals[row + x + u32(1)] = total;
+    }
+}
+
+// ...then down each column
+@compute
+@workgroup_size(64)
+fn column_sums(@builtin(global_invocation_id) position: vec3<u32>) {
+    let x = position.x;
+    if x > config.width {
+        return;
+    }
+    let stride = config.width + u32(1);
+    totals[x] = u32(0);
+    var total = u32(0);
+    for (var y = u32(1); y <= config.height; y++) {
+        total += totals[y * stride + x];
+        totals[y * stride + x] = total;
+    }
+}
+
+// How far the neighbourhood reaches horizontally, dy rows away
+fn half_width(dy: u32) -> u32 {
+    if rule.shape == u32(0) {
+        return rule.radius;
+    } else if rule.shape == u32(1) {
+        return rule.radius - dy;
+    }
+    let limit = rule.radius * rule.radius + rule.radius - dy * dy;
+    var width = u32(sqrt(f32(limit)));
+    // Correct for any rounding in the square root
+    while width * width > limit {
+        width--;
+    }
+    while (width + u32(1)) * (width + u32(1)) <= limit {
+        width++;
+    }
+    return width;
+}
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) position: vec3<u32>) {
+    if position.x >= config.width || position.y >= config.height {
+        return;
+    }
+
+    let index = from_xy(position.x, position.y);
+    let old_value = input_buffer[index];
+
+    let x = i32(position.x);
+    let y = i32(position.y);
+    let radius = i32(rule.radius);
+    let top = u32(max(y - radius, 0));
+    let bottom = u32(min(y + radius + 1, i32(config.height)));
+    var total = u32(0);
+    if rule.shape == u32(0) {
+        // A square is a single rectangle
+        let left = u32(max(x - radius, 0));
+        let right = u32(min(x + radius + 1, i32(config.width)));
+        total = count(left, top, right, bottom);
+    } else {
+        // Other shapes are counted a row at a time
+        for (var row = top; row < bottom; row++) {
+            let width = i32(half_width(u32(abs(i32(row) - y))));
+            let left = u32(max(x - width, 0));
+            let right = u32(min(x + width + 1, i32(config.width)));
+            total += count(left, row, right, row + u32(1));
+        }
+    }
+    if rule.middle == u32(0) && old_value == u32(1) {
+        total--;
+    }
+
+    var new_value: u32;
+    if old_value == u32(0) {
+        new_value = u32(total >= rule.birth_min && total <= rule.birth_max);
+    } else if old_value == u32(1) {
+        if total >= rule.survival_min && total <= rule.survival_max {
+            new_value = u32(1);
+        } else {
+            new_value = u32(2) % config.states;
+        }
+    } else {
+        new_value = (old_value + u32(1)) % config.states;
+    }
+
+    output_buffer[index] = new_value;
+}
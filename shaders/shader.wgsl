@@ -24,8 +24,244 @@ var texture: texture_2d<f32>;
 @group(0) @binding(1)
 var texture_sampler: sampler;
 
+struct Settings {
+    hexagonal: u32,
+    // 0 to colour by state, 1 by age, 2 by activity
+    mode: u32,
+    ramp_colours: u32,
+    ramp_generations: u32,
+    // The board position at the centre of the window, and pixels per cell
+    camera: vec4<f32>,
+    // The size of the window in pixels
+    viewport: vec2<f32>,
+    grid: u32,
+    // Cells between bold grid lines, or 0 for none
+    grid_bold: u32,
+    // Whether to average cells together when zoomed out
+    smoothing: u32,
+    // Where the viewport starts in the target, in pixels
+    origin: vec2<f32>,
+    // The selection and the area a paste would cover, as (x, y, width,
+    // height) in cells, with a width of 0 when there's none
+    selection: vec4<i32>,
+    paste: vec4<i32>,
+    // Colours for individual states, used where alpha is set
+    colours: array<vec4<f32>, 256>,
+    ramp: array<vec4<f32>, 8>,
+}
+
+@group(0) @binding(2)
+var<uniform> settings: Settings;
+
+struct Palette {
+    live: vec4<f32>,
+    dead: vec4<f32>,
+    border: vec4<f32>,
+    grid: vec4<f32>,
+    selection: vec4<f32>,
+    states: u32,
+    // A gradient for states from 2 up
+    state_colours: array<vec4<f32>, 16>,
+}
+
+@group(0) @binding(3)
+var<uniform> palette: Palette;
+
+// Every cell's colour, with a mip chain of averages for zoomed out views
+@group(0) @binding(4)
+var colour_texture: texture_2d<f32>;
+
+@group(0) @binding(5)
+var colour_sampler: sampler;
+
+// The top level of the colour texture, written by colour_cells
+@group(0) @binding(6)
+var colour_output: texture_storage_2d<rgba8unorm, write>;
+
+// The states of the pattern being pasted, in reading order
+@group(1) @binding(0)
+var<storage, read> paste_states: array<u32>;
+
+fn ramp(t: f32) -> vec4<f32> {
+    if settings.ramp_colours == u32(0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let last = settings.ramp_colours - u32(1);
+    let position = clamp(t, 0.0, 1.0) * f32(last);
+    let stop = min(u32(position), last);
+    return mix(settings.ramp[stop], settings.ramp[min(stop + u32(1), last)], position - f32(stop));
+}
+
+fn state_gradient(t: f32) -> vec4<f32> {
+    if palette.states == u32(0) {
+        return palette.live;
+    }
+    let last = palette.states - u32(1);
+    let position = clamp(t, 0.0, 1.0) * f32(last);
+    let stop = min(u32(position), last);
+    return mix(palette.state_colours[stop], palette.state_colours[min(stop + u32(1), last)], position - f32(stop));
+}
+
+// Cells are (state, states, age, generations since death), with -1 for cells
+// that have never lived, and a state of -1 outside the board
+fn colour(cell: vec4<f32>) -> vec4<f32> {
+    let state = cell.x;
+    let states = cell.y;
+    let age = cell.z;
+    let since_death = cell.w;
+    let generations = f32(settings.ramp_generations);
+    if state < 0.0 {
+        return palette.border;
+    }
+
+    if settings.mode == u32(1) && state != 0.0 {
+        return ramp((age - 1.0) / generations);
+    }
+    if settings.mode == u32(2) {
+        if state != 0.0 {
+            return ramp(1.0 - (age - 1.0) / generations);
+        } else if since_death >= 0.0 && since_death <= generations {
+            // The recently dead fade out as they cool
+            let heat = 1.0 - (since_death - 1.0) / generations;
+            return mix(palette.dead, ramp(heat), heat);
+        }
+    }
+    if state < 256.0 && settings.colours[u32(state)].a > 0.0 {
+        return settings.colours[u32(state)];
+    }
+    if state == 1.0 {
+        return palette.live;
+    } else if state >= 2.0 {
+        // Dying cells run through the gradient as they decay
+        return state_gradient((state - 2.0) / max(states - 3.0, 1.0));
+    }
+    return palette.dead;
+}
+
+const SQRT_3: f32 = 1.7320508;
+
+// Lays the board out as hexagons, with cell (x, y) centred at
+// (x - y / 2, y * sqrt(3) / 2) so the six neighbours are equidistant
+fn hex_cell(point: vec2<f32>) -> vec2<i32> {
+    // Round to the nearest hexagon in cube coordinates
+    let q = point.x - point.y / SQRT_3;
+    let r = 2.0 * point.y / SQRT_3;
+    let cube = vec3<f32>(q, -q - r, r);
+    var rounded = round(cube);
+    let error = abs(rounded - cube);
+    if error.x > error.y && error.x > error.z {
+        rounded.x = -rounded.y - rounded.z;
+    } else if error.z > error.y {
+        rounded.z = -rounded.x - rounded.y;
+    }
+
+    let y = i32(rounded.z);
+    return vec2<i32>(i32(rounded.x) + y, y);
+}
+
+fn cell_at(point: vec2<f32>) -> vec2<i32> {
+    if settings.hexagonal != u32(0) {
+        return hex_cell(point);
+    }
+    return vec2<i32>(floor(point));
+}
+
+// The cell's texel, or a state of -1 outside the board
+fn load(cell: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(texture));
+    if cell.x < 0 || cell.y < 0 || cell.x >= size.x || cell.y >= size.y {
+        return vec4<f32>(-1.0, 0.0, 0.0, -1.0);
+    }
+    return textureLoad(texture, cell, 0);
+}
+
+fn inside(cell: vec2<i32>, area: vec4<i32>) -> bool {
+    return area.z > 0 && cell.x >= area.x && cell.y >= area.y && cell.x < area.x + area.z && cell.y < area.y + area.w;
+}
+
+// Tints the selection, and shows the pattern being pasted over the cells it
+// would replace
+fn overlay(cell: vec2<i32>, shade: vec4<f32>) -> vec4<f32> {
+    var result = shade;
+    if inside(cell, settings.selection) {
+        result = mix(result, palette.selection, 0.4);
+    }
+    let paste = settings.paste;
+    if inside(cell, paste) {
+        let offset = cell - paste.xy;
+        let state = paste_states[offset.y * paste.z + offset.x];
+        if state != u32(0) {
+            let states = max(load(cell).y, 2.0);
+            result = mix(result, colour(vec4<f32>(f32(state), states, 1.0, 0.0)), 0.75);
+        } else {
+            result = mix(result, palette.selection, 0.25);
+        }
+    }
+    return result;
+}
+
+// Whether a grid line is bold, as a weight
+fn bold(line: f32) -> f32 {
+    if settings.grid_bold != u32(0) && u32(line) % settings.grid_bold == u32(0) {
+        return 1.0;
+    }
+    return 0.0;
+}
+
+// Draws lines between cells over the board. They fade in as cells grow past
+// a few pixels across, so they never crowd into moiré when zoomed out, with
+// bold lines fading in sooner since they're further apart.
+fn grid(point: vec2<f32>, colour: vec4<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(texture));
+    if settings.grid == u32(0) || point.x < 0.0 || point.y < 0.0 || point.x > size.x || point.y > size.y {
+        return colour;
+    }
+    let zoom = settings.camera.z;
+    let line = round(point);
+    // How much of a pixel wide line covers this pixel
+    let coverage = clamp(1.0 - abs(point - line) * zoom, vec2<f32>(0.0), vec2<f32>(1.0));
+    let thin = 0.5 * smoothstep(4.0, 12.0, zoom);
+    let heavy = smoothstep(8.0, 24.0, zoom * f32(settings.grid_bold));
+    let weight = coverage * max(vec2<f32>(thin), heavy * vec2<f32>(bold(line.x), bold(line.y)));
+    return mix(colour, palette.grid, max(weight.x, weight.y));
+}
+
+// Colours every cell into the top of the mip chain, ready to be averaged down
+// for the levels below
+@compute
+@workgroup_size(8, 8)
+fn colour_cells(@builtin(global_invocation_id) position: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(texture));
+    let cell = vec2<i32>(position.xy);
+    if cell.x >= size.x || cell.y >= size.y {
+        return;
+    }
+    textureStore(colour_output, cell, colour(textureLoad(texture, cell, 0)));
+}
+
+// The average colour of the cells under a pixel, so that cells smaller than a
+// pixel show up as shades rather than flickering in and out
+fn smooth_colour(point: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(texture));
+    if point.x < 0.0 || point.y < 0.0 || point.x >= size.x || point.y >= size.y {
+        return palette.border;
+    }
+    return textureSampleLevel(colour_texture, colour_sampler, point / size, -log2(settings.camera.z));
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let life = textureSample(texture, texture_sampler, in.tex_coords).x;
-    return vec4<f32>(life, life, life, 1.0);
+    // The fragment position is in pixels from the top left of the window
+    let point = settings.camera.xy + (in.clip_position.xy - settings.origin - settings.viewport / 2.0) / settings.camera.z;
+    let cell = cell_at(point);
+    if settings.hexagonal != u32(0) {
+        return overlay(cell, colour(load(cell)));
+    }
+    var shade: vec4<f32>;
+    if settings.smoothing != u32(0) && settings.camera.z < 1.0 {
+        shade = smooth_colour(point);
+    } else {
+        shade = colour(load(cell));
+    }
+    return grid(point, overlay(cell, shade));
 }
@@ -0,0 +1,72 @@
+// Draws egui's triangles, whose colours are premultiplied sRGB
+
+struct Screen {
+    // In points, which the vertex positions are given in
+    size: vec2<f32>,
+    _padding: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+@group(1) @binding(0)
+var texture: texture_2d<f32>;
+@group(1) @binding(1)
+var texture_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+}
+
+// From sRGB in 0-255 to linear in 0-1
+fn linear(srgb: vec3<f32>) -> vec3<f32> {
+    let lower = srgb / 3294.6;
+    let higher = pow((srgb + 14.025) / 269.025, vec3<f32>(2.4));
+    return select(higher, lower, srgb < vec3<f32>(10.31475));
+}
+
+// From linear in 0-1 to sRGB in 0-1
+fn srgb(linear: vec3<f32>) -> vec3<f32> {
+    let lower = linear * 12.92;
+    let higher = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, linear < vec3<f32>(0.0031308));
+}
+
+@vertex
+fn vs_main(
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) colour: u32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(
+        2.0 * position.x / screen.size.x - 1.0,
+        1.0 - 2.0 * position.y / screen.size.y,
+        0.0,
+        1.0,
+    );
+    out.tex_coords = tex_coords;
+    let rgba = vec4<f32>(
+        f32(colour & 255u),
+        f32((colour >> 8u) & 255u),
+        f32((colour >> 16u) & 255u),
+        f32((colour >> 24u) & 255u),
+    );
+    out.colour = vec4<f32>(linear(rgba.rgb), rgba.a / 255.0);
+    return out;
+}
+
+// For sRGB targets, which convert back from linear on writing
+@fragment
+fn fs_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.colour * textureSample(texture, texture_sampler, in.tex_coords);
+}
+
+// For other targets, which take sRGB as it is
+@fragment
+fn fs_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let colour = in.colour * textureSample(texture, texture_sampler, in.tex_coords);
+    return vec4<f32>(srgb(colour.rgb / max(colour.a, 0.0001)) * colour.a, colour.a);
+}
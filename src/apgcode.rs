@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+
+use crate::{
+    generate::{Error, Generator, RawData},
+    pattern::Pattern,
+    rule::{Family, Isotropic, Rule},
+};
+
+// Objects that haven't settled into a cycle by then get no code
+const MAX_PERIOD: u32 = 1024;
+
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+fn digit_value(chr: char) -> Option<u32> {
+    chr.to_digit(36)
+}
+
+// Extended Wechsler format, without the `xs4_` style prefix
+fn decode_wechsler(code: &str) -> Result<Pattern, Error> {
+    let mut pattern = Pattern::new();
+    let (mut x, mut strip) = (0, 0);
+    let mut chars = code.chars();
+    let bad_code = |message: String| Error::Parse { line: 1, message };
+
+    while let Some(chr) = chars.next() {
+        match chr {
+            'w' => x += 2,
+            'x' => x += 3,
+            'y' => {
+                let count = chars
+                    .next()
+                    .and_then(digit_value)
+                    .ok_or_else(|| bad_code("`y` must be followed by a digit or letter".into()))?;
+                x += 4 + count as i32;
+            }
+            'z' => {
+                x = 0;
+                strip += 1;
+            }
+            _ => {
+                let bits = digit_value(chr)
+                    .filter(|&bits| bits < 32)
+                    .ok_or_else(|| bad_code(format!("unexpected character {chr:?}")))?;
+                for row in 0..5 {
+                    if bits & (1 << row) != 0 {
+                        pattern.set(x, strip * 5 + row, 1);
+                    }
+                }
+                x += 1;
+            }
+        }
+    }
+    Ok(pattern)
+}
+
+fn encode_wechsler(pattern: &Pattern) -> String {
+    let pattern = pattern.normalise();
+    let Some((_, _, max_x, max_y)) = pattern.bounding_box() else {
+        return String::from("0");
+    };
+
+    let strips = (0..=max_y / 5)
+        .map(|strip| {
+            let columns = (0..=max_x)
+                .map(|x| {
+                    let bits = (0..5)
+                        .filter(|row| pattern.get(x, strip * 5 + row) != 0)
+                        .fold(0, |bits, row| bits | (1 << row));
+                    DIGITS[bits] as char
+                })
+                .collect::<String>();
+            columns.trim_end_matches('0').to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let mut code = String::new();
+    for chunk in strips.join("z").split_inclusive(|chr| chr != '0') {
+        let zeros = chunk.chars().take_while(|&chr| chr == '0').count();
+        let mut remaining = zeros;
+        while remaining >= 4 {
+            let run = remaining.min(39);
+            code.push('y');
+            code.push(DIGITS[run - 4] as char);
+            remaining -= run;
+        }
+        code.push_str(["", "0", "w", "x"][remaining]);
+        code.push_str(&chunk[zeros..]);
+    }
+    code
+}
+
+// Compact codes sort first, then ties break alphabetically
+fn canonical(pattern: &Pattern) -> String {
+    let orientations = [
+        pattern.clone(),
+        pattern.rotate_90(),
+        pattern.rotate_180(),
+        pattern.rotate_270(),
+        pattern.flip_horizontal(),
+        pattern.flip_vertical(),
+        pattern.flip_diagonal(),
+        pattern.flip_diagonal().rotate_180(),
+    ];
+    orientations
+        .iter()
+        .map(encode_wechsler)
+        .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+        .unwrap()
+}
+
+// One generation of a two state rule on an unbounded plane, looking up each
+// cell's neighbourhood in the same table the compute shader uses
+fn step(pattern: &Pattern, rule: &Isotropic) -> Pattern {
+    let mut neighbourhoods = HashMap::<(i32, i32), usize>::new();
+    for (x, y, _) in pattern.cells() {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                // This cell is at (1 - dx, 1 - dy) in the neighbourhood of (x + dx, y + dy)
+                let bit = (1 - dy) * 3 + (1 - dx);
+                *neighbourhoods.entry((x + dx, y + dy)).or_default() |= 1 << bit;
+            }
+        }
+    }
+    neighbourhoods
+        .into_iter()
+        .filter(|&(_, neighbourhood)| rule.next(neighbourhood))
+        .map(|(position, _)| position)
+        .collect()
+}
+
+// Takes xs, xp and xq codes, or a bare extended Wechsler code
+pub fn decode(apgcode: &str) -> Result<Pattern, Error> {
+    let Some((prefix, code)) = apgcode.split_once('_') else {
+        return decode_wechsler(apgcode);
+    };
+    let number = ["xs", "xp", "xq"]
+        .iter()
+        .find_map(|kind| prefix.strip_prefix(kind))
+        .ok_or_else(|| Error::Invalid(format!("unsupported apgcode prefix {prefix:?}")))?;
+    let number = match number.parse::<u32>() {
+        Ok(number) if number > 0 => number,
+        _ => {
+            return Err(Error::Invalid(format!(
+                "bad population or period in {prefix:?}"
+            )))
+        }
+    };
+    let pattern = decode_wechsler(code)?;
+    // Still lifes give their population rather than a period
+    if prefix.starts_with("xs") && number as usize != pattern.population() {
+        return Err(Error::Invalid(format!(
+            "{apgcode} has {} cells, not {number}",
+            pattern.population()
+        )));
+    }
+    Ok(pattern)
+}
+
+// Classifies the object as a still life (xs), oscillator (xp) or spaceship
+// (xq) under a two state rule and encodes its canonical phase and
+// orientation. B0 rules are refused, as they'd fill the unbounded plane.
+pub fn encode(pattern: &Pattern, rule: &Rule) -> Result<String, Error> {
+    let rule = match rule {
+        Rule::Isotropic(isotropic)
+            if isotropic.states == 2
+                && isotropic.family == Family::Single
+                && !isotropic.next(0) =>
+        {
+            isotropic
+        }
+        rule => {
+            return Err(Error::Invalid(format!(
+                "apgcodes are only for two state rules without B0, not {rule}"
+            )))
+        }
+    };
+    let empty = || Error::Invalid("an empty pattern has no apgcode".into());
+    let start = pattern.normalise();
+    let (start_x, start_y, _, _) = pattern.bounding_box().ok_or_else(empty)?;
+    let mut phases = vec![start.clone()];
+    let mut current = pattern.clone();
+
+    for period in 1..=MAX_PERIOD {
+        current = step(&current, rule);
+        let (x, y, _, _) = current.bounding_box().ok_or_else(|| {
+            Error::Invalid(format!("the pattern dies out by generation {period}"))
+        })?;
+        let normalised = current.normalise();
+        if normalised == start {
+            let code = phases
+                .iter()
+                .map(canonical)
+                .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+                .unwrap();
+            let prefix = if (x, y) != (start_x, start_y) {
+                format!("xq{period}")
+            } else if period == 1 {
+                format!("xs{}", pattern.population())
+            } else {
+                format!("xp{period}")
+            };
+            return Ok(format!("{prefix}_{code}"));
+        }
+        phases.push(normalised);
+    }
+    Err(Error::Invalid(format!(
+        "the pattern doesn't repeat within {MAX_PERIOD} generations"
+    )))
+}
+
+// The live cells of a rectangle of the board, as an apgcode
+pub fn encode_region(
+    data: &[u32],
+    config: &crate::life::Config,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<String, Error> {
+    let pattern = (y..y.saturating_add(height).min(config.height))
+        .flat_map(|row| {
+            (x..x.saturating_add(width).min(config.width)).map(move |column| (column, row))
+        })
+        .filter(|&(column, row)| data[(row * config.width + column) as usize] != 0)
+        .map(|(column, row)| (column as i32, row as i32))
+        .collect::<Pattern>();
+    encode(&pattern, &config.rule)
+}
+
+pub struct Apgcode {
+    pub code: String,
+    pub x_offset: u32,
+    pub y_offset: u32,
+}
+
+impl Generator for Apgcode {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let pattern = decode(&self.code)?;
+        RawData::from_relative(
+            pattern.cells().map(|(x, y, _)| (x, y)),
+            self.x_offset,
+            self.y_offset,
+        )
+        .generate(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::Config;
+
+    fn life() -> Rule {
+        "B3/S23".parse().unwrap()
+    }
+
+    #[test]
+    fn encodes_known_objects() {
+        assert_eq!(encode(&Pattern::block(), &life()).unwrap(), "xs4_33");
+        assert_eq!(encode(&Pattern::glider(), &life()).unwrap(), "xq4_153");
+        let blinker = [(0, 0), (1, 0), (2, 0)].into_iter().collect::<Pattern>();
+        assert_eq!(encode(&blinker, &life()).unwrap(), "xp2_7");
+    }
+
+    #[test]
+    fn round_trips_known_codes() {
+        for code in [
+            "xs4_33",
+            "xs6_696",
+            "xp2_7",
+            "xq4_153",
+            "xq4_6frc",
+            "xp15_4r4z4r4",
+        ] {
+            assert_eq!(encode(&decode(code).unwrap(), &life()).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn decodes_bare_wechsler_codes() {
+        assert_eq!(decode("33").unwrap(), Pattern::block());
+        assert_eq!(decode("153").unwrap().population(), 5);
+    }
+
+    #[test]
+    fn rejects_bad_prefixes() {
+        for code in ["xy4_33", "xs_33", "xp0_7", "xs5_33"] {
+            assert!(matches!(decode(code), Err(Error::Invalid(_))), "{code}");
+        }
+    }
+
+    #[test]
+    fn only_encodes_two_state_rules() {
+        for rule in [
+            "B3/S23/C3",
+            "B03/S23",
+            "B3/S23QuadLife",
+            "R2,C0,M1,S5..8,B6..8,NM",
+        ] {
+            let rule = rule.parse::<Rule>().unwrap();
+            assert!(matches!(
+                encode(&Pattern::block(), &rule),
+                Err(Error::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_patterns_without_a_period() {
+        assert!(encode(&Pattern::new(), &life()).is_err());
+        let domino = [(0, 0), (1, 0)].into_iter().collect::<Pattern>();
+        assert!(encode(&domino, &life()).is_err());
+    }
+
+    #[test]
+    fn encodes_regions_running_off_the_board() {
+        let config = Config::new(8, 8);
+        let data = Apgcode {
+            code: String::from("xq4_153"),
+            x_offset: 5,
+            y_offset: 5,
+        }
+        .generate(&config)
+        .unwrap();
+        let code = encode_region(&data, &config, 4, 4, u32::MAX, u32::MAX).unwrap();
+        assert_eq!(code, "xq4_153");
+    }
+}
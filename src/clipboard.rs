@@ -0,0 +1,101 @@
+use arboard::Clipboard;
+
+use crate::{
+    generate::{self, Error},
+    pattern::Pattern,
+    rle,
+};
+
+// A pattern read from the clipboard, with the rule it was written for if it
+// said
+pub struct Pasted {
+    pub pattern: Pattern,
+    pub rule: Option<String>,
+}
+
+// RLE, as copied from LifeWiki or Golly, or plaintext. Plaintext is nothing
+// but '!' comment lines and rows of '.' and 'O', so anything else, like RLE's
+// '#' comments, header or runs such as "bo$", is taken as RLE.
+pub fn parse(text: &str) -> Result<Pasted, Error> {
+    let text = text.trim();
+    let rle = !text
+        .lines()
+        .map(str::trim)
+        .all(|line| line.starts_with('!') || line.chars().all(|chr| matches!(chr, '.' | 'O')));
+    if rle {
+        let file = rle::parse(text)?;
+        Ok(Pasted {
+            pattern: file.pattern,
+            rule: file.rule,
+        })
+    } else {
+        Ok(Pasted {
+            pattern: generate::parse_plaintext(text)?,
+            rule: None,
+        })
+    }
+}
+
+// The system clipboard, which under X11 only keeps what was copied while it's
+// alive, so should be kept around rather than made for each copy
+pub struct SystemClipboard(Clipboard);
+
+fn clipboard_error(err: arboard::Error) -> Error {
+    Error::Invalid(format!("clipboard: {err}"))
+}
+
+impl SystemClipboard {
+    pub fn new() -> Result<Self, Error> {
+        Clipboard::new().map(Self).map_err(clipboard_error)
+    }
+
+    pub fn paste(&mut self) -> Result<Pasted, Error> {
+        let text = self.0.get_text().map_err(clipboard_error)?;
+        let pasted = parse(&text)?;
+        if pasted.pattern.is_empty() {
+            return Err(Error::Invalid("the clipboard has no pattern in it".into()));
+        }
+        Ok(pasted)
+    }
+
+    pub fn copy(&mut self, pattern: &Pattern, rule: Option<&str>) -> Result<(), Error> {
+        self.0
+            .set_text(rle::write(pattern, rule))
+            .map_err(clipboard_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_rle_with_comments() {
+        let pasted =
+            parse("#N Glider\n#C A comment\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!").unwrap();
+        assert_eq!(pasted.pattern, Pattern::glider());
+        assert_eq!(pasted.rule.as_deref(), Some("B3/S23"));
+    }
+
+    #[test]
+    fn reads_plaintext_with_a_name() {
+        let pasted = parse("!Name: Glider\n!\n.O\n..O\nOOO\n").unwrap();
+        assert_eq!(pasted.pattern, Pattern::glider());
+        assert_eq!(pasted.rule, None);
+    }
+
+    #[test]
+    fn reads_bare_rle_data() {
+        let pasted = parse("bo$2bo$3o").unwrap();
+        assert_eq!(pasted.pattern, Pattern::glider());
+        assert_eq!(parse("bo$").unwrap().pattern.population(), 1);
+    }
+
+    #[test]
+    fn reads_rle_starting_with_x_prefixed_states() {
+        let pasted = parse("xA2o$\nxB!").unwrap();
+        assert_eq!(pasted.pattern.get(0, 0), 217);
+        assert_eq!(pasted.pattern.get(0, 1), 218);
+        assert_eq!(pasted.pattern.population(), 4);
+    }
+}
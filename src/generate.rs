@@ -1,10 +1,57 @@
-#![allow(dead_code)]
-use std::fs;
+use std::{fmt, fs, io};
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{pattern::Pattern, rule::RuleError};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    OutOfBounds { x: i64, y: i64 },
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{err}"),
+            Error::Parse { line, message } => write!(f, "line {line}: {message}"),
+            Error::OutOfBounds { x, y } => write!(f, "cell ({x}, {y}) is outside the board"),
+            Error::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
 
 pub trait Generator {
-    fn generate(self, config: &crate::life::Config) -> Vec<u32>;
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error>;
+}
+
+pub fn empty_board(config: &crate::life::Config) -> Vec<u32> {
+    vec![0; (config.width * config.height) as usize]
+}
+
+pub(crate) fn set_cell(
+    data: &mut [u32],
+    config: &crate::life::Config,
+    x: i64,
+    y: i64,
+    state: u32,
+) -> Result<(), Error> {
+    if !(0..config.width as i64).contains(&x) || !(0..config.height as i64).contains(&y) {
+        return Err(Error::OutOfBounds { x, y });
+    }
+    data[(y * config.width as i64 + x) as usize] = state;
+    Ok(())
 }
 
 pub struct RawData {
@@ -13,24 +60,203 @@ pub struct RawData {
     y_offset: u32,
 }
 
+impl RawData {
+    pub fn new(
+        positions: impl IntoIterator<Item = (u32, u32)>,
+        x_offset: u32,
+        y_offset: u32,
+    ) -> Self {
+        Self {
+            positions: positions.into_iter().collect(),
+            x_offset,
+            y_offset,
+        }
+    }
+
+    // Rows of 'O' or '*' for live cells and '.' or ' ' for dead ones
+    pub fn from_ascii(rows: &[&str], x_offset: u32, y_offset: u32) -> Result<Self, Error> {
+        let mut positions = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, chr) in row.chars().enumerate() {
+                match chr {
+                    'O' | '*' => positions.push((x as u32, y as u32)),
+                    '.' | ' ' => {}
+                    _ => {
+                        return Err(Error::Parse {
+                            line: y + 1,
+                            message: format!("unexpected character {chr:?}"),
+                        })
+                    }
+                }
+            }
+        }
+        Ok(Self::new(positions, x_offset, y_offset))
+    }
+
+    // Coordinates relative to an arbitrary centre, as in Life 1.06 files,
+    // shifted so the bounding box starts at the offset
+    pub fn from_relative(
+        positions: impl IntoIterator<Item = (i32, i32)>,
+        x_offset: u32,
+        y_offset: u32,
+    ) -> Self {
+        let positions = positions.into_iter().collect::<Vec<_>>();
+        let min_x = positions.iter().map(|&(x, _)| x).min().unwrap_or(0);
+        let min_y = positions.iter().map(|&(_, y)| y).min().unwrap_or(0);
+        // Any two i32s are less than u32::MAX apart, though only in i64, and
+        // generate reports whatever lands past the edge of the board
+        Self::new(
+            positions.into_iter().map(|(x, y)| {
+                (
+                    (x as i64 - min_x as i64) as u32,
+                    (y as i64 - min_y as i64) as u32,
+                )
+            }),
+            x_offset,
+            y_offset,
+        )
+    }
+}
+
 impl Generator for RawData {
-    fn generate(self, config: &crate::life::Config) -> Vec<u32> {
-        let mut data = vec![0; (config.width * config.height) as usize];
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let mut data = empty_board(config);
         for (x, y) in self.positions {
-            data[((y + self.y_offset) * config.width + (x + self.x_offset)) as usize] = 1;
+            set_cell(
+                &mut data,
+                config,
+                x as i64 + self.x_offset as i64,
+                y as i64 + self.y_offset as i64,
+                1,
+            )?;
         }
-        data
+        Ok(data)
+    }
+}
+
+// FNV-1a, so the same seed string gives the same cells on every platform
+pub(crate) fn seeded_rng(seed: &str) -> ChaCha8Rng {
+    let hash = seed.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    ChaCha8Rng::seed_from_u64(hash)
+}
+
+// The chance of each cell being alive, for random fills
+pub(crate) fn check_density(density: f64) -> Result<(), Error> {
+    if !(0.0..=1.0).contains(&density) {
+        return Err(Error::Invalid(format!(
+            "density {density} isn't between 0 and 1"
+        )));
     }
+    Ok(())
 }
 
-pub struct Random(pub f64);
+// A reproducible random fill of the whole board
+pub struct Random {
+    pub seed: String,
+    pub density: f64,
+}
 
 impl Generator for Random {
-    fn generate(self, config: &crate::life::Config) -> Vec<u32> {
-        let mut rng = rand::thread_rng();
-        (0..(config.width * config.height))
-            .map(|_| rng.gen_bool(self.0) as u32)
-            .collect::<Vec<_>>()
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        check_density(self.density)?;
+        let mut rng = seeded_rng(&self.seed);
+        Ok((0..(config.width * config.height))
+            .map(|_| rng.gen_bool(self.density) as u32)
+            .collect::<Vec<_>>())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+    C1,
+    C2,
+    C4,
+    D2,
+    D4,
+    D8,
+}
+
+impl Symmetry {
+    // Every image of (x, y) inside a width x height region under this symmetry
+    fn orbit(self, x: u32, y: u32, width: u32, height: u32) -> Vec<(u32, u32)> {
+        let (fx, fy) = (width - 1 - x, height - 1 - y);
+        match self {
+            Symmetry::C1 => vec![(x, y)],
+            Symmetry::C2 => vec![(x, y), (fx, fy)],
+            Symmetry::C4 => vec![(x, y), (fy, x), (fx, fy), (y, fx)],
+            Symmetry::D2 => vec![(x, y), (fx, y)],
+            Symmetry::D4 => vec![(x, y), (fx, y), (x, fy), (fx, fy)],
+            Symmetry::D8 => vec![
+                (x, y),
+                (fy, x),
+                (fx, fy),
+                (y, fx),
+                (fx, y),
+                (y, x),
+                (x, fy),
+                (fy, fx),
+            ],
+        }
+    }
+}
+
+// A reproducible random soup, filling a region centred on the board
+pub struct Soup {
+    pub seed: String,
+    pub density: f64,
+    pub width: u32,
+    pub height: u32,
+    pub symmetry: Symmetry,
+}
+
+impl Soup {
+    // Like apgsearch's C1 soups, a 16x16 region at 50% density
+    pub fn c1(seed: impl Into<String>) -> Self {
+        Self {
+            seed: seed.into(),
+            density: 0.5,
+            width: 16,
+            height: 16,
+            symmetry: Symmetry::C1,
+        }
+    }
+}
+
+impl Generator for Soup {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        if matches!(self.symmetry, Symmetry::C4 | Symmetry::D8) && self.width != self.height {
+            return Err(Error::Invalid("C4 and D8 soups must be square".into()));
+        }
+        if self.width > config.width || self.height > config.height {
+            return Err(Error::Invalid(format!(
+                "a {}x{} soup doesn't fit on a {}x{} board",
+                self.width, self.height, config.width, config.height
+            )));
+        }
+        check_density(self.density)?;
+        let mut data = empty_board(config);
+        let mut rng = seeded_rng(&self.seed);
+        let x_offset = (config.width - self.width) / 2;
+        let y_offset = (config.height - self.height) / 2;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let orbit = self.symmetry.orbit(x, y, self.width, self.height);
+                // Only draw once per orbit, from its smallest cell
+                if orbit.iter().any(|&(ox, oy)| (oy, ox) < (y, x)) {
+                    continue;
+                }
+                if rng.gen_bool(self.density) {
+                    for (ox, oy) in orbit {
+                        data[((oy + y_offset) * config.width + (ox + x_offset)) as usize] = 1;
+                    }
+                }
+            }
+        }
+
+        Ok(data)
     }
 }
 
@@ -40,25 +266,197 @@ pub struct Plaintext {
     pub y_offset: u32,
 }
 
+// Rows of 'O' for live cells and '.' for dead ones, with comment lines
+// starting with '!'
+pub fn parse_plaintext(text: &str) -> Result<Pattern, Error> {
+    let mut pattern = Pattern::new();
+    let lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, x)| !x.starts_with('!'));
+
+    for (y, (line_number, line)) in lines.enumerate() {
+        for (x, chr) in line.trim_end().chars().enumerate() {
+            let state = match chr {
+                '.' => 0,
+                'O' => 1,
+                _ => {
+                    return Err(Error::Parse {
+                        line: line_number + 1,
+                        message: format!("unexpected character {chr:?}"),
+                    })
+                }
+            };
+            pattern.set(x as i32, y as i32, state);
+        }
+    }
+    Ok(pattern)
+}
+
 impl Generator for Plaintext {
-    fn generate(self, config: &crate::life::Config) -> Vec<u32> {
-        let mut data = vec![0; (config.width * config.height) as usize];
-        let text = fs::read_to_string(self.source).unwrap();
-        let lines = text.lines().filter(|x| !x.starts_with("!"));
-
-        for (y, line) in lines.enumerate() {
-            for (x, chr) in line.chars().enumerate() {
-                let index = ((y as u32 + self.y_offset) * config.width + (x as u32 + self.x_offset))
-                    as usize;
-                data[index] = match chr {
-                    '.' => 0,
-                    'O' => 1,
-                    _ => panic!("Bad char"),
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let mut data = empty_board(config);
+        let pattern = parse_plaintext(&fs::read_to_string(self.source)?)?;
+        for (x, y, state) in pattern.cells() {
+            set_cell(
+                &mut data,
+                config,
+                x as i64 + self.x_offset as i64,
+                y as i64 + self.y_offset as i64,
+                state,
+            )?;
+        }
+        Ok(data)
+    }
+}
+
+// One `x y` pair per line, relative to an arbitrary centre
+pub fn parse_life106(text: &str) -> Result<Vec<(i32, i32)>, Error> {
+    let mut positions = vec![];
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad_line = || Error::Parse {
+            line: line_number + 1,
+            message: format!("expected an `x y` pair, found {line:?}"),
+        };
+        let mut numbers = line.split_whitespace().map(|n| n.parse::<i32>());
+        match (numbers.next(), numbers.next(), numbers.next()) {
+            (Some(Ok(x)), Some(Ok(y)), None) => positions.push((x, y)),
+            _ => return Err(bad_line()),
+        }
+    }
+    Ok(positions)
+}
+
+pub struct Life106 {
+    pub source: &'static str,
+    pub x_offset: u32,
+    pub y_offset: u32,
+}
+
+impl Generator for Life106 {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let positions = parse_life106(&fs::read_to_string(self.source)?)?;
+        RawData::from_relative(positions, self.x_offset, self.y_offset).generate(config)
+    }
+}
+
+pub struct Life105File {
+    pub description: Vec<String>,
+    // As a B/S rulestring, like RLE's `rule =`, though the file gives
+    // survival before birth, as in "23/3"
+    pub rule: Option<String>,
+    pub positions: Vec<(i32, i32)>,
+}
+
+// `#P x y` blocks of '.' and '*' rows, each block offset from an arbitrary centre
+pub fn parse_life105(text: &str) -> Result<Life105File, Error> {
+    let mut file = Life105File {
+        description: vec![],
+        rule: None,
+        positions: vec![],
+    };
+    // Files without any #P line put their only block at the centre
+    let mut block = (0, 0);
+    let mut row = 0;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        let bad_line = |message: String| Error::Parse {
+            line: line_number + 1,
+            message,
+        };
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut parts = directive.splitn(2, char::is_whitespace);
+            let kind = parts.next().unwrap_or("");
+            let rest = parts.next().unwrap_or("").trim();
+            match kind {
+                "Life" => {}
+                "D" | "C" => file.description.push(rest.to_string()),
+                "N" => file.rule = Some("B3/S23".to_string()),
+                "R" => match rest.split_once('/') {
+                    Some((survival, birth)) => {
+                        file.rule = Some(format!("B{}/S{}", birth.trim(), survival.trim()))
+                    }
+                    None => return Err(bad_line(format!("bad rule {rest:?}"))),
+                },
+                "P" => {
+                    let numbers = rest
+                        .split_whitespace()
+                        .map(|n| n.parse::<i32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|err| bad_line(err.to_string()))?;
+                    match numbers[..] {
+                        [x, y] => block = (x, y),
+                        _ => return Err(bad_line(format!("bad block position {rest:?}"))),
+                    }
+                    row = 0;
                 }
+                _ => {}
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (x_offset, y_offset) = block;
+        for (x, chr) in line.chars().enumerate() {
+            match chr {
+                '*' => file.positions.push((x_offset + x as i32, y_offset + row)),
+                '.' => {}
+                _ => return Err(bad_line(format!("unexpected character {chr:?}"))),
             }
         }
+        row += 1;
+    }
+    Ok(file)
+}
 
-        data
+pub struct Life105 {
+    pub source: &'static str,
+    pub x_offset: u32,
+    pub y_offset: u32,
+}
+
+impl Life105 {
+    pub fn read(&self) -> Result<Life105File, Error> {
+        parse_life105(&fs::read_to_string(self.source)?)
+    }
+
+    // The board, with the config switched to the file's rule if it gives one
+    pub fn load(
+        self,
+        config: &crate::life::Config,
+    ) -> Result<(crate::life::Config, Vec<u32>), Error> {
+        let file = self.read()?;
+        let mut config = config.clone();
+        if let Some(rule) = file.rule {
+            config.rule = rule
+                .parse()
+                .map_err(|err: RuleError| Error::Invalid(err.to_string()))?;
+        }
+        let data = RawData::from_relative(file.positions, self.x_offset, self.y_offset)
+            .generate(&config)?;
+        Ok((config, data))
+    }
+}
+
+// Refuses files for a different rule than the config's, rather than running
+// them under the wrong one; `Life105::load` switches to the file's rule
+impl Generator for Life105 {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let source = self.source;
+        let (loaded, data) = self.load(config)?;
+        if loaded.rule != config.rule {
+            return Err(Error::Invalid(format!(
+                "{source} is for {}, not {}",
+                loaded.rule, config.rule
+            )));
+        }
+        Ok(data)
     }
 }
 
@@ -106,3 +504,196 @@ pub fn glider_gun() -> RawData {
         y_offset: 10,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{life::Config, pattern::Pattern};
+
+    const WIDTH: u32 = 20;
+
+    fn config() -> Config {
+        Config::new(WIDTH, WIDTH)
+    }
+
+    fn soup(seed: &str, symmetry: Symmetry) -> Vec<u32> {
+        Soup {
+            symmetry,
+            ..Soup::c1(seed)
+        }
+        .generate(&config())
+        .unwrap()
+    }
+
+    #[test]
+    fn repeats_soups_from_the_same_seed() {
+        assert_eq!(soup("seed", Symmetry::C1), soup("seed", Symmetry::C1));
+        assert_ne!(soup("seed", Symmetry::C1), soup("seeds", Symmetry::C1));
+        let random = |seed: &str| {
+            Random {
+                seed: seed.into(),
+                density: 0.3,
+            }
+            .generate(&config())
+            .unwrap()
+        };
+        assert_eq!(random("seed"), random("seed"));
+        assert_ne!(random("seed"), random("seeds"));
+    }
+
+    #[test]
+    fn keeps_each_symmetry() {
+        // Reflections and rotations of the 16x16 region
+        let flip_x = |x: u32, y: u32| (15 - x, y);
+        let flip_y = |x: u32, y: u32| (x, 15 - y);
+        let half_turn = |x: u32, y: u32| (15 - x, 15 - y);
+        let quarter_turn = |x: u32, y: u32| (15 - y, x);
+        let transpose = |x: u32, y: u32| (y, x);
+        type Image = fn(u32, u32) -> (u32, u32);
+        let cases: [(Symmetry, &[Image]); 5] = [
+            (Symmetry::C2, &[half_turn]),
+            (Symmetry::C4, &[quarter_turn]),
+            (Symmetry::D2, &[flip_x]),
+            (Symmetry::D4, &[flip_x, flip_y]),
+            (Symmetry::D8, &[flip_x, flip_y, transpose]),
+        ];
+        for (symmetry, images) in cases {
+            let data = soup("symmetric", symmetry);
+            // The region is centred on the board
+            let cell = |(x, y): (u32, u32)| data[((y + 2) * WIDTH + x + 2) as usize];
+            let population = data.iter().filter(|&&state| state == 1).count();
+            assert!(population > 0 && population < 256);
+            for y in 0..16 {
+                for x in 0..16 {
+                    for image in images {
+                        assert_eq!(cell(image(x, y)), cell((x, y)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_densities_and_sizes() {
+        for density in [-0.1, 1.5, f64::NAN] {
+            let random = Random {
+                seed: "seed".into(),
+                density,
+            };
+            assert!(matches!(random.generate(&config()), Err(Error::Invalid(_))));
+        }
+        let wide = Soup {
+            width: 21,
+            ..Soup::c1("seed")
+        };
+        assert!(matches!(wide.generate(&config()), Err(Error::Invalid(_))));
+        let oblong = Soup {
+            height: 8,
+            symmetry: Symmetry::D8,
+            ..Soup::c1("seed")
+        };
+        assert!(matches!(oblong.generate(&config()), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn places_raw_data_at_its_offset() {
+        let data = RawData::new([(0, 0), (2, 1)], 3, 4)
+            .generate(&config())
+            .unwrap();
+        assert_eq!(data.iter().sum::<u32>(), 2);
+        assert_eq!(data[(4 * WIDTH + 3) as usize], 1);
+        assert_eq!(data[(5 * WIDTH + 5) as usize], 1);
+    }
+
+    #[test]
+    fn reads_ascii_rows() {
+        // Rows can be ragged, as trailing dead cells are often left off
+        let glider = RawData::from_ascii(&[".O", "..*", "OOO"], 0, 0).unwrap();
+        let expected = RawData::new([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], 0, 0);
+        assert_eq!(
+            glider.generate(&config()).unwrap(),
+            expected.generate(&config()).unwrap()
+        );
+        assert!(matches!(
+            RawData::from_ascii(&["O.", "Ox"], 0, 0),
+            Err(Error::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn reports_cells_off_the_board() {
+        let data = RawData::new([(0, 0), (20, 3)], 0, 0);
+        assert!(matches!(
+            data.generate(&config()),
+            Err(Error::OutOfBounds { x: 20, y: 3 })
+        ));
+        let shifted = RawData::new([(15, 0)], 5, 0);
+        assert!(matches!(
+            shifted.generate(&config()),
+            Err(Error::OutOfBounds { x: 20, y: 0 })
+        ));
+    }
+
+    #[test]
+    fn measures_relative_positions_from_the_bounding_box() {
+        let data = RawData::from_relative([(-1, -1), (1, 0)], 2, 2)
+            .generate(&config())
+            .unwrap();
+        assert_eq!(data[(2 * WIDTH + 2) as usize], 1);
+        assert_eq!(data[(3 * WIDTH + 4) as usize], 1);
+        // The full width of i32 apart
+        let wide = RawData::from_relative([(i32::MIN, 0), (i32::MAX, 0)], 0, 0);
+        assert!(matches!(
+            wide.generate(&config()),
+            Err(Error::OutOfBounds { x, y: 0 }) if x == u32::MAX as i64
+        ));
+    }
+
+    #[test]
+    fn parses_life106() {
+        let text = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        let positions = parse_life106(text).unwrap();
+        assert_eq!(positions, [(0, -1), (1, 0), (-1, 1), (0, 1), (1, 1)]);
+        assert_eq!(
+            positions.into_iter().collect::<Pattern>().normalise(),
+            Pattern::glider()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_life106_lines() {
+        assert!(matches!(
+            parse_life106("0 0\n1 2 3\n"),
+            Err(Error::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_life105_blocks() {
+        let text = "#Life 1.05\n#D A glider\n#N\n#P -1 -1\n.*.\n..*\n#P -1 1\n***\n";
+        let file = parse_life105(text).unwrap();
+        assert_eq!(file.description, ["A glider"]);
+        assert_eq!(file.rule.as_deref(), Some("B3/S23"));
+        assert_eq!(
+            file.positions.into_iter().collect::<Pattern>().normalise(),
+            Pattern::glider()
+        );
+    }
+
+    #[test]
+    fn converts_life105_rules() {
+        let file = parse_life105("#Life 1.05\n#R 23/36\n*\n").unwrap();
+        assert_eq!(file.rule.as_deref(), Some("B36/S23"));
+        assert!(matches!(
+            parse_life105("#Life 1.05\n#R 23\n"),
+            Err(Error::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn leaves_the_rule_out_when_life105_doesnt_give_one() {
+        let file = parse_life105("#Life 1.05\n**\n**\n").unwrap();
+        assert_eq!(file.rule, None);
+        assert_eq!(file.positions.len(), 4);
+    }
+}
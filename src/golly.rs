@@ -0,0 +1,649 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::{generate::Error, rule::Neighbourhood};
+
+// The same limit as Golly
+const MAX_STATES: u32 = 256;
+
+// Cell offsets, in the order each section lists a cell and its neighbours
+const TABLE_MOORE: [(i32, i32); 9] = [
+    (0, 0),
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+const TABLE_VON_NEUMANN: [(i32, i32); 5] = [(0, 0), (0, -1), (1, 0), (0, 1), (-1, 0)];
+const TREE_MOORE: [(i32, i32); 9] = [
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+    (0, -1),
+    (-1, 0),
+    (1, 0),
+    (0, 1),
+    (0, 0),
+];
+const TREE_VON_NEUMANN: [(i32, i32); 5] = [(0, -1), (-1, 0), (1, 0), (0, 1), (0, 0)];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    // As Golly evaluates a @TABLE: for each input and state there is a bitmask
+    // of the transitions accepting it, and the first transition set in all of a
+    // cell's masks gives its new state
+    Table { masks: Vec<u32>, outputs: Vec<u32> },
+    // A flattened @TREE, where each node holds one entry per state: the offset
+    // of a child node, or the new state at the bottom level
+    Tree { nodes: Vec<u32>, root: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GollyRule {
+    pub name: String,
+    pub states: u32,
+    pub neighbourhood: Neighbourhood,
+    pub lookup: Lookup,
+    // From the @COLORS section, as (state, rgb)
+    pub colours: Vec<(u32, [u8; 3])>,
+    // The whole file, so that it can be saved along with a board
+    pub source: String,
+}
+
+impl GollyRule {
+    // Where the lookup reads each of its inputs from, relative to the cell
+    pub fn offsets(&self) -> &'static [(i32, i32)] {
+        match (&self.lookup, self.neighbourhood) {
+            (Lookup::Table { .. }, Neighbourhood::VonNeumann) => &TABLE_VON_NEUMANN,
+            (Lookup::Table { .. }, _) => &TABLE_MOORE,
+            (Lookup::Tree { .. }, Neighbourhood::VonNeumann) => &TREE_VON_NEUMANN,
+            (Lookup::Tree { .. }, _) => &TREE_MOORE,
+        }
+    }
+
+    // The length of each table bitmask in u32s
+    pub fn words(&self) -> usize {
+        match &self.lookup {
+            Lookup::Table { outputs, .. } => outputs.len().div_ceil(32),
+            Lookup::Tree { .. } => 0,
+        }
+    }
+
+    // The next state of a cell, given the states of its 3x3 block in reading
+    // order
+    pub fn next(&self, block: [u32; 9]) -> u32 {
+        let inputs = self
+            .offsets()
+            .iter()
+            .map(|&(dx, dy)| block[((dy + 1) * 3 + dx + 1) as usize]);
+        match &self.lookup {
+            Lookup::Table { masks, outputs } => {
+                let words = self.words();
+                let inputs = inputs.collect::<Vec<_>>();
+                for word in 0..words {
+                    let matches =
+                        inputs
+                            .iter()
+                            .enumerate()
+                            .fold(u32::MAX, |matches, (input, &state)| {
+                                let index = (input * self.states as usize + state as usize) * words;
+                                matches & masks[index + word]
+                            });
+                    if matches != 0 {
+                        return outputs[word * 32 + matches.trailing_zeros() as usize];
+                    }
+                }
+                // Cells no transition matches stay as they are
+                block[4]
+            }
+            Lookup::Tree { nodes, root } => {
+                inputs.fold(*root, |node, state| nodes[(node + state) as usize])
+            }
+        }
+    }
+}
+
+type Lines<'a> = [(usize, &'a str)];
+
+fn bad_line(line: usize) -> impl Fn(String) -> Error {
+    move |message| Error::Parse { line, message }
+}
+
+fn parse_states(value: &str, line: usize) -> Result<u32, Error> {
+    let states = value
+        .trim()
+        .parse::<u32>()
+        .map_err(|err| bad_line(line)(err.to_string()))?;
+    if !(2..=MAX_STATES).contains(&states) {
+        return Err(bad_line(line)(format!(
+            "number of states must be between 2 and {MAX_STATES}"
+        )));
+    }
+    Ok(states)
+}
+
+// Every rearrangement of the neighbours a symmetry allows, as the index each
+// neighbour is taken from. Neighbours run clockwise from north. The permute
+// symmetry depends on the transition, so is left to `distinct_permutations`.
+fn symmetries(name: &str, neighbours: usize) -> Result<Vec<Vec<usize>>, String> {
+    let (rotations, reflect): (Vec<usize>, bool) = match (name, neighbours) {
+        ("none", _) => (vec![0], false),
+        ("reflect_horizontal", _) => (vec![0], true),
+        ("rotate4", 8) => (vec![0, 2, 4, 6], false),
+        ("rotate4reflect", 8) => (vec![0, 2, 4, 6], true),
+        ("rotate8", 8) => ((0..8).collect(), false),
+        ("rotate8reflect", 8) => ((0..8).collect(), true),
+        ("rotate4", 4) => ((0..4).collect(), false),
+        ("rotate4reflect", 4) => ((0..4).collect(), true),
+        _ => {
+            return Err(format!(
+                "unsupported symmetries {name:?} for {neighbours} neighbours"
+            ))
+        }
+    };
+    let mut permutations = rotations
+        .iter()
+        .map(|&k| (0..neighbours).map(|i| (i + k) % neighbours).collect())
+        .collect::<Vec<_>>();
+    if reflect {
+        permutations.extend(rotations.iter().map(|&k| {
+            (0..neighbours)
+                .map(|i| (k + neighbours - i) % neighbours)
+                .collect()
+        }));
+    }
+    Ok(permutations)
+}
+
+// Every distinct rearrangement of a transition's neighbours, for the permute
+// symmetry. Neighbours with the same set of states are interchangeable, so
+// this steps through the orderings of the multiset of sets rather than all n!
+// orderings of the neighbours.
+fn distinct_permutations(sets: &[Vec<u32>]) -> Vec<Vec<usize>> {
+    // Each neighbour's set, named by the first neighbour with the same one
+    let groups = sets
+        .iter()
+        .map(|set| sets.iter().position(|other| other == set).unwrap())
+        .collect::<Vec<_>>();
+    let mut order = groups.clone();
+    order.sort_unstable();
+    let mut permutations = vec![];
+    loop {
+        let mut used = vec![false; sets.len()];
+        let permutation = order
+            .iter()
+            .map(|&group| {
+                let index = (0..sets.len())
+                    .find(|&index| groups[index] == group && !used[index])
+                    .unwrap();
+                used[index] = true;
+                index
+            })
+            .collect();
+        permutations.push(permutation);
+
+        // On to the next ordering in lexicographic order, if there is one
+        let Some(pivot) = (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) else {
+            return permutations;
+        };
+        let successor = (pivot..order.len())
+            .rev()
+            .find(|&i| order[pivot - 1] < order[i])
+            .unwrap();
+        order.swap(pivot - 1, successor);
+        order[pivot..].reverse();
+    }
+}
+
+struct Table<'a> {
+    states: Option<u32>,
+    neighbourhood: Neighbourhood,
+    symmetries: &'a str,
+    variables: HashMap<&'a str, Vec<u32>>,
+    permutations: Vec<Vec<usize>>,
+    // Each transition's inputs as sets of states, and its output
+    transitions: Vec<(Vec<Vec<u32>>, u32)>,
+    seen: HashSet<Vec<Vec<u32>>>,
+}
+
+impl<'a> Table<'a> {
+    fn neighbours(&self) -> usize {
+        match self.neighbourhood {
+            Neighbourhood::VonNeumann => 4,
+            _ => 8,
+        }
+    }
+
+    fn values(&self, token: &str) -> Result<Vec<u32>, String> {
+        let states = self.states.ok_or("n_states must come first")?;
+        if let Ok(state) = token.parse::<u32>() {
+            if state >= states {
+                return Err(format!("state {state} is out of range"));
+            }
+            return Ok(vec![state]);
+        }
+        self.variables
+            .get(token)
+            .cloned()
+            .ok_or_else(|| format!("unknown variable {token:?}"))
+    }
+
+    fn variable(&mut self, definition: &'a str) -> Result<(), String> {
+        let (name, values) = definition
+            .split_once('=')
+            .ok_or("expected `var name={...}`")?;
+        let values = values
+            .trim()
+            .strip_prefix('{')
+            .and_then(|values| values.strip_suffix('}'))
+            .ok_or("variable values must be in braces")?;
+        let mut states = vec![];
+        for token in values.split(',') {
+            states.extend(self.values(token.trim())?);
+        }
+        states.sort_unstable();
+        states.dedup();
+        self.variables.insert(name.trim(), states);
+        Ok(())
+    }
+
+    fn transition(&mut self, line: &str) -> Result<(), String> {
+        let tokens = if line.contains(',') {
+            line.split(',').map(str::trim).collect::<Vec<_>>()
+        } else {
+            // Rules with at most 10 states can leave out the commas
+            line.split("")
+                .filter(|token| !token.trim().is_empty())
+                .collect()
+        };
+        let inputs = self.neighbours() + 1;
+        if tokens.len() != inputs + 1 {
+            return Err(format!(
+                "expected {} states, found {}",
+                inputs + 1,
+                tokens.len()
+            ));
+        }
+        let permute = self.symmetries == "permute";
+        if self.permutations.is_empty() && !permute {
+            self.permutations = symmetries(self.symmetries, self.neighbours())?;
+        }
+
+        // A variable used more than once takes the same value everywhere in
+        // the transition
+        let mut bound = tokens
+            .iter()
+            .filter(|token| self.variables.contains_key(*token))
+            .filter(|token| tokens.iter().filter(|other| other == token).count() > 1)
+            .copied()
+            .collect::<Vec<_>>();
+        bound.sort_unstable();
+        bound.dedup();
+        let mut bindings = vec![HashMap::new()];
+        for name in bound {
+            let values = self.values(name)?;
+            bindings = bindings
+                .into_iter()
+                .flat_map(|binding: HashMap<&str, u32>| {
+                    values.iter().map(move |&value| {
+                        let mut binding = binding.clone();
+                        binding.insert(name, value);
+                        binding
+                    })
+                })
+                .collect();
+        }
+
+        for binding in bindings {
+            let sets = tokens
+                .iter()
+                .map(|token| match binding.get(token) {
+                    Some(&value) => Ok(vec![value]),
+                    None => self.values(token),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let [output] = sets[inputs][..] else {
+                return Err("the output must be a state or a variable used as an input".into());
+            };
+            let distinct;
+            let permutations = if permute {
+                distinct = distinct_permutations(&sets[1..inputs]);
+                &distinct
+            } else {
+                &self.permutations
+            };
+            for permutation in permutations {
+                let mut permuted = vec![sets[0].clone()];
+                permuted.extend(permutation.iter().map(|&i| sets[i + 1].clone()));
+                // Only the first transition for any inputs can ever match
+                if self.seen.insert(permuted.clone()) {
+                    self.transitions.push((permuted, output));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &'a str, line_number: usize) -> Result<(), Error> {
+        let bad_line = bad_line(line_number);
+        if let Some(definition) = line.strip_prefix("var ") {
+            return self.variable(definition).map_err(bad_line);
+        }
+        match line.split_once(':') {
+            Some(("n_states", value)) => self.states = Some(parse_states(value, line_number)?),
+            Some(("neighborhood", value)) => {
+                self.neighbourhood = match value.trim() {
+                    "Moore" => Neighbourhood::Moore,
+                    "vonNeumann" => Neighbourhood::VonNeumann,
+                    other => return Err(bad_line(format!("unsupported neighborhood {other:?}"))),
+                }
+            }
+            Some(("symmetries", value)) => self.symmetries = value.trim(),
+            Some((key, _)) => return Err(bad_line(format!("unknown key {key:?}"))),
+            None => self.transition(line).map_err(bad_line)?,
+        }
+        Ok(())
+    }
+}
+
+fn parse_table(lines: &Lines) -> Result<(u32, Neighbourhood, Lookup), Error> {
+    let mut table = Table {
+        states: None,
+        neighbourhood: Neighbourhood::Moore,
+        symmetries: "none",
+        variables: HashMap::new(),
+        permutations: vec![],
+        transitions: vec![],
+        seen: HashSet::new(),
+    };
+    for &(line_number, line) in lines {
+        table.line(line, line_number)?;
+    }
+    let states = table
+        .states
+        .ok_or_else(|| Error::Invalid("@TABLE is missing n_states".into()))?;
+
+    let inputs = table.neighbours() + 1;
+    let words = table.transitions.len().div_ceil(32);
+    let mut masks = vec![0; inputs * states as usize * words];
+    for (transition, (sets, _)) in table.transitions.iter().enumerate() {
+        for (input, set) in sets.iter().enumerate() {
+            for &state in set {
+                let index = (input * states as usize + state as usize) * words;
+                masks[index + transition / 32] |= 1 << (transition % 32);
+            }
+        }
+    }
+    let outputs = table
+        .transitions
+        .into_iter()
+        .map(|(_, output)| output)
+        .collect();
+    Ok((
+        states,
+        table.neighbourhood,
+        Lookup::Table { masks, outputs },
+    ))
+}
+
+fn parse_tree(lines: &Lines) -> Result<(u32, Neighbourhood, Lookup), Error> {
+    let mut states = None;
+    let mut neighbours = None;
+    let mut nodes = vec![];
+    // The offset and level of each node read so far
+    let mut offsets = vec![];
+    let mut levels = vec![];
+
+    for &(line_number, line) in lines {
+        let bad_line = bad_line(line_number);
+        if let Some((key, value)) = line.split_once('=') {
+            match key.trim() {
+                "num_states" => states = Some(parse_states(value, line_number)?),
+                "num_neighbors" => {
+                    neighbours = Some(
+                        value
+                            .trim()
+                            .parse::<u32>()
+                            .map_err(|err| bad_line(err.to_string()))?,
+                    )
+                }
+                "num_nodes" => {}
+                key => return Err(bad_line(format!("unknown key {key:?}"))),
+            }
+            continue;
+        }
+
+        let states = states.ok_or_else(|| bad_line("num_states must come first".into()))?;
+        let numbers = line
+            .split_whitespace()
+            .map(|n| n.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| bad_line(err.to_string()))?;
+        let [level, ref entries @ ..] = numbers[..] else {
+            continue;
+        };
+        if entries.len() != states as usize {
+            return Err(bad_line(format!(
+                "expected {states} entries, found {}",
+                entries.len()
+            )));
+        }
+        offsets.push(nodes.len() as u32);
+        levels.push(level);
+        for &entry in entries {
+            if level == 1 {
+                if entry >= states {
+                    return Err(bad_line(format!("state {entry} is out of range")));
+                }
+                nodes.push(entry);
+            } else if levels.get(entry as usize) == Some(&(level - 1)) {
+                nodes.push(offsets[entry as usize]);
+            } else {
+                return Err(bad_line(format!("bad child node {entry}")));
+            }
+        }
+    }
+
+    let states = states.ok_or_else(|| Error::Invalid("@TREE is missing num_states".into()))?;
+    let neighbourhood = match neighbours {
+        Some(8) => Neighbourhood::Moore,
+        Some(4) => Neighbourhood::VonNeumann,
+        _ => return Err(Error::Invalid("num_neighbors must be 4 or 8".into())),
+    };
+    // The root node decides on the cell and all its neighbours
+    match (levels.last(), neighbours) {
+        (Some(&level), Some(neighbours)) if level == neighbours + 1 => {}
+        _ => {
+            return Err(Error::Invalid(
+                "@TREE has the wrong number of levels".into(),
+            ))
+        }
+    }
+    let root = *offsets.last().unwrap();
+    Ok((states, neighbourhood, Lookup::Tree { nodes, root }))
+}
+
+fn parse_colours(lines: &Lines, states: u32) -> Result<Vec<(u32, [u8; 3])>, Error> {
+    let mut colours = vec![];
+    for &(line_number, line) in lines {
+        let bad_line = bad_line(line_number);
+        let numbers = line
+            .split_whitespace()
+            .map(|n| n.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| bad_line(err.to_string()))?;
+        let channel = |value: u32| {
+            u8::try_from(value).map_err(|_| bad_line(format!("colour {value} is out of range")))
+        };
+        match numbers[..] {
+            [state, r, g, b] => colours.push((state, [channel(r)?, channel(g)?, channel(b)?])),
+            // A gradient across the live states
+            [r1, g1, b1, r2, g2, b2] => {
+                let (start, end) = ([r1, g1, b1], [r2, g2, b2]);
+                for state in 1..states {
+                    let t = (state - 1) as f32 / (states - 2).max(1) as f32;
+                    let mut colour = [0; 3];
+                    for i in 0..3 {
+                        let value = start[i] as f32 + (end[i] as f32 - start[i] as f32) * t;
+                        colour[i] = channel(value.round() as u32)?;
+                    }
+                    colours.push((state, colour));
+                }
+            }
+            _ => return Err(bad_line("expected `state r g b` or a gradient".into())),
+        }
+    }
+    Ok(colours)
+}
+
+pub fn parse(text: &str) -> Result<GollyRule, Error> {
+    let mut name = None;
+    let mut sections = HashMap::<&str, Vec<(usize, &str)>>::new();
+    let mut section = "";
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('@') {
+            let (header, rest) = header
+                .split_once(char::is_whitespace)
+                .unwrap_or((header, ""));
+            if header == "RULE" {
+                name = Some(rest.trim().to_string());
+            }
+            section = header;
+            continue;
+        }
+        let line = line.split('#').next().unwrap().trim();
+        if !line.is_empty() {
+            sections
+                .entry(section)
+                .or_default()
+                .push((line_number + 1, line));
+        }
+    }
+
+    let name = name.ok_or_else(|| Error::Invalid("missing @RULE line".into()))?;
+    let (states, neighbourhood, lookup) = if let Some(lines) = sections.get("TABLE") {
+        parse_table(lines)?
+    } else if let Some(lines) = sections.get("TREE") {
+        parse_tree(lines)?
+    } else {
+        return Err(Error::Invalid(format!(
+            "rule {name:?} has no @TABLE or @TREE section"
+        )));
+    };
+    let colours = match sections.get("COLORS") {
+        Some(lines) => parse_colours(lines, states)?,
+        None => vec![],
+    };
+    Ok(GollyRule {
+        name,
+        states,
+        neighbourhood,
+        lookup,
+        colours,
+        source: text.lines().map(|line| format!("{line}\n")).collect(),
+    })
+}
+
+pub fn read(path: impl AsRef<Path>) -> Result<GollyRule, Error> {
+    parse(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::Isotropic;
+
+    // Each neighbourhood of the 3x3 block, in reading order like the table
+    // the compute shader uses
+    fn block(neighbourhood: usize) -> [u32; 9] {
+        std::array::from_fn(|i| (neighbourhood >> i) as u32 & 1)
+    }
+
+    #[test]
+    fn expands_permute_tables() {
+        let rule = parse(
+            "@RULE PermuteLife\n\
+             @TABLE\n\
+             n_states:2\n\
+             neighborhood:Moore\n\
+             symmetries:permute\n\
+             var a={0,1}\nvar b={0,1}\nvar c={0,1}\nvar d={0,1}\n\
+             var e={0,1}\nvar f={0,1}\nvar g={0,1}\nvar h={0,1}\n\
+             0,1,1,1,0,0,0,0,0,1\n\
+             1,1,1,0,0,0,0,0,0,1\n\
+             1,1,1,1,0,0,0,0,0,1\n\
+             1,a,b,c,d,e,f,g,h,0\n",
+        )
+        .unwrap();
+        let life = Isotropic::life();
+        for neighbourhood in 0..512 {
+            assert_eq!(
+                rule.next(block(neighbourhood)),
+                life.next(neighbourhood) as u32,
+                "{neighbourhood:09b}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_keeps_distinct_permutations() {
+        let (zero, one, any) = (vec![0], vec![1], vec![0, 1]);
+        let sets = [one.clone(), one, zero.clone(), zero, any];
+        // 5! / (2! 2! 1!)
+        assert_eq!(distinct_permutations(&sets).len(), 30);
+    }
+
+    #[test]
+    fn walks_trees() {
+        // Every cell takes the state of its northern neighbour
+        let rule = parse(
+            "@RULE North\n\
+             @TREE\n\
+             num_states=2\n\
+             num_neighbors=4\n\
+             num_nodes=9\n\
+             1 0 0\n2 0 0\n3 1 1\n4 2 2\n\
+             1 1 1\n2 4 4\n3 5 5\n4 6 6\n\
+             5 3 7\n",
+        )
+        .unwrap();
+        assert_eq!(rule.neighbourhood, Neighbourhood::VonNeumann);
+        for neighbourhood in 0..512 {
+            let block = block(neighbourhood);
+            assert_eq!(rule.next(block), block[1]);
+        }
+    }
+
+    #[test]
+    fn rejects_trees_with_too_few_levels() {
+        let text = "@RULE Short\n@TREE\nnum_states=2\nnum_neighbors=4\n1 0 1\n";
+        assert!(matches!(parse(text), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn runs_wireworld() {
+        let rule = read("rules/WireWorld.rule").unwrap();
+        assert_eq!((rule.name.as_str(), rule.states), ("WireWorld", 4));
+        assert_eq!(rule.colours.len(), 4);
+        let wire = |heads: usize| {
+            let mut block = [3; 9];
+            for cell in [0, 2, 6, 8].into_iter().take(heads) {
+                block[cell] = 1;
+            }
+            rule.next(block)
+        };
+        assert_eq!(rule.next([0, 0, 0, 0, 1, 0, 0, 0, 0]), 2);
+        assert_eq!(rule.next([1, 1, 1, 1, 2, 1, 1, 1, 1]), 3);
+        assert_eq!(rule.next([1, 1, 1, 1, 0, 1, 1, 1, 1]), 0);
+        assert_eq!((wire(0), wire(1), wire(2), wire(3)), (3, 1, 1, 3));
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem,
+};
+
+use crate::life::Config;
+
+// The board at one point, with the size and rule it had then, as runs of
+// (state, length) in reading order, since most boards are mostly empty space
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub config: Config,
+    pub generation: u64,
+    runs: Vec<(u32, u32)>,
+}
+
+impl Snapshot {
+    pub fn new(config: &Config, data: &[u32], generation: u64) -> Self {
+        let mut runs: Vec<(u32, u32)> = vec![];
+        for &state in data {
+            match runs.last_mut() {
+                Some((last, length)) if *last == state => *length += 1,
+                _ => runs.push((state, 1)),
+            }
+        }
+        Self {
+            config: config.clone(),
+            generation,
+            runs,
+        }
+    }
+
+    pub fn data(&self) -> Vec<u32> {
+        self.runs
+            .iter()
+            .flat_map(|&(state, length)| std::iter::repeat_n(state, length as usize))
+            .collect()
+    }
+
+    // Roughly how many bytes it takes up
+    pub fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.runs.len() * mem::size_of::<(u32, u32)>()
+    }
+}
+
+// Boards from before each edit or run, to go back to, and the ones undone
+// since, to go forward to again. The oldest are forgotten once they take up
+// more than the limit in bytes.
+pub struct History {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: vec![],
+            limit,
+        }
+    }
+
+    // Remembers the board from before a change, and forgets whatever was
+    // undone, which the change replaces
+    pub fn record(&mut self, snapshot: Snapshot) {
+        self.redo.clear();
+        self.undo.push_back(snapshot);
+        self.trim();
+    }
+
+    // Swaps the current board for the one before the last change
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.undo.pop_back()?;
+        self.redo.push(current);
+        self.trim();
+        Some(snapshot)
+    }
+
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push_back(current);
+        self.trim();
+        Some(snapshot)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn memory(&self) -> usize {
+        self.undo.iter().chain(&self.redo).map(Snapshot::size).sum()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    // Forgets the oldest undo steps first, then the furthest redo steps
+    fn trim(&mut self) {
+        let mut memory = self.memory();
+        while memory > self.limit {
+            let dropped = match self.undo.pop_front() {
+                Some(snapshot) => snapshot,
+                None if !self.redo.is_empty() => self.redo.remove(0),
+                None => break,
+            };
+            memory -= dropped.size();
+        }
+    }
+}
+
+// Boards saved every so many generations of a run, so that any earlier
+// generation can be rebuilt by stepping forward from the one before it, and
+// the boards left by edits, which can't be. Past the limit in bytes, every
+// other keyframe of the run is dropped and the interval doubled, so the whole
+// run stays reachable at the cost of longer replays.
+pub struct Keyframes {
+    frames: BTreeMap<u64, Snapshot>,
+    edits: BTreeSet<u64>,
+    interval: u64,
+    limit: usize,
+}
+
+impl Keyframes {
+    pub fn new(interval: u64, limit: usize) -> Self {
+        Self {
+            frames: BTreeMap::new(),
+            edits: BTreeSet::new(),
+            interval: interval.max(1),
+            limit,
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    // Whether a keyframe should be saved at this generation of a run
+    pub fn due(&self, generation: u64) -> bool {
+        generation.is_multiple_of(self.interval) && !self.frames.contains_key(&generation)
+    }
+
+    pub fn insert(&mut self, snapshot: Snapshot) {
+        self.frames.insert(snapshot.generation, snapshot);
+        self.trim();
+    }
+
+    // Saves the board as changed by an edit, forgetting the keyframes from
+    // then on, which no longer follow from it
+    pub fn edit(&mut self, snapshot: Snapshot) {
+        self.frames.split_off(&snapshot.generation);
+        self.edits.split_off(&snapshot.generation);
+        self.edits.insert(snapshot.generation);
+        self.insert(snapshot);
+    }
+
+    // The board an edit left at a generation, which replaying past it should
+    // pick up from
+    pub fn edited(&self, generation: u64) -> Option<&Snapshot> {
+        self.edits
+            .contains(&generation)
+            .then(|| &self.frames[&generation])
+    }
+
+    // The latest keyframe at or before a generation
+    pub fn before(&self, generation: u64) -> Option<&Snapshot> {
+        self.frames
+            .range(..=generation)
+            .next_back()
+            .map(|(_, snapshot)| snapshot)
+    }
+
+    pub fn memory(&self) -> usize {
+        self.frames.values().map(Snapshot::size).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.edits.clear();
+    }
+
+    fn keep(&self, generation: u64) -> bool {
+        generation.is_multiple_of(self.interval) || self.edits.contains(&generation)
+    }
+
+    fn trim(&mut self) {
+        while self.memory() > self.limit
+            && self
+                .frames
+                .keys()
+                .any(|&generation| generation != 0 && !self.edits.contains(&generation))
+        {
+            self.interval *= 2;
+            let dropped: Vec<u64> = self
+                .frames
+                .keys()
+                .copied()
+                .filter(|&generation| !self.keep(generation))
+                .collect();
+            for generation in dropped {
+                self.frames.remove(&generation);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(generation: u64) -> Snapshot {
+        let data = [0, 0, 1, 1, 1, 0, 2, 0, 0];
+        Snapshot::new(&Config::new(3, 3), &data, generation)
+    }
+
+    #[test]
+    fn stores_boards_as_runs() {
+        let snapshot = snapshot(0);
+        assert_eq!(snapshot.runs, [(0, 2), (1, 3), (0, 1), (2, 1), (0, 2)]);
+        assert_eq!(snapshot.data(), [0, 0, 1, 1, 1, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn undoes_and_redoes_in_order() {
+        let mut history = History::new(usize::MAX);
+        history.record(snapshot(0));
+        history.record(snapshot(1));
+        assert_eq!(history.undo(snapshot(2)).unwrap().generation, 1);
+        assert_eq!(history.undo(snapshot(1)).unwrap().generation, 0);
+        assert_eq!(history.undo(snapshot(0)), None);
+        assert_eq!(history.redo(snapshot(0)).unwrap().generation, 1);
+        assert_eq!(history.redo(snapshot(1)).unwrap().generation, 2);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn forgets_what_was_undone_on_a_new_change() {
+        let mut history = History::new(usize::MAX);
+        history.record(snapshot(0));
+        history.undo(snapshot(1));
+        assert!(history.can_redo());
+        history.record(snapshot(0));
+        assert!(!history.can_redo());
+        assert!(history.can_undo());
+    }
+
+    #[test]
+    fn forgets_the_oldest_steps_past_the_limit() {
+        let size = snapshot(0).size();
+        let mut history = History::new(size * 2);
+        for generation in 0..3 {
+            history.record(snapshot(generation));
+        }
+        assert_eq!(history.memory(), size * 2);
+        assert_eq!(history.undo(snapshot(3)).unwrap().generation, 2);
+        assert_eq!(history.undo(snapshot(2)).unwrap().generation, 1);
+        // The first step went when the third was recorded
+        assert_eq!(history.undo(snapshot(1)), None);
+
+        // Then the furthest redo step goes first
+        history.set_limit(size);
+        assert_eq!(history.memory(), size);
+        assert_eq!(history.redo(snapshot(1)).unwrap().generation, 2);
+        assert_eq!(history.redo(snapshot(2)), None);
+    }
+
+    #[test]
+    fn finds_the_keyframe_before_a_generation() {
+        let mut keyframes = Keyframes::new(10, usize::MAX);
+        for generation in [0, 10, 20] {
+            assert!(keyframes.due(generation));
+            keyframes.insert(snapshot(generation));
+        }
+        assert!(!keyframes.due(10));
+        assert!(!keyframes.due(15));
+        assert_eq!(keyframes.before(9).unwrap().generation, 0);
+        assert_eq!(keyframes.before(10).unwrap().generation, 10);
+        assert_eq!(keyframes.before(25).unwrap().generation, 20);
+    }
+
+    #[test]
+    fn forgets_keyframes_after_an_edit() {
+        let mut keyframes = Keyframes::new(10, usize::MAX);
+        for generation in [0, 10, 20, 30] {
+            keyframes.insert(snapshot(generation));
+        }
+        keyframes.edit(snapshot(15));
+        assert_eq!(keyframes.before(35).unwrap().generation, 15);
+        assert!(keyframes.edited(15).is_some());
+        assert!(keyframes.edited(10).is_none());
+        // An earlier edit replaces the later one too
+        keyframes.edit(snapshot(5));
+        assert!(keyframes.edited(15).is_none());
+        assert_eq!(keyframes.before(35).unwrap().generation, 5);
+    }
+
+    #[test]
+    fn doubles_the_interval_past_the_limit() {
+        let size = snapshot(0).size();
+        let mut keyframes = Keyframes::new(1, size * 4);
+        for generation in 0..8 {
+            if keyframes.due(generation) {
+                keyframes.insert(snapshot(generation));
+            }
+        }
+        // Every other keyframe went when the fifth was saved
+        assert_eq!(keyframes.interval(), 2);
+        assert_eq!(keyframes.memory(), size * 4);
+        assert_eq!(keyframes.before(7).unwrap().generation, 6);
+        assert_eq!(keyframes.before(3).unwrap().generation, 2);
+        // Edits are kept whatever the interval
+        keyframes.edit(snapshot(5));
+        keyframes.insert(snapshot(8));
+        keyframes.insert(snapshot(12));
+        assert_eq!(keyframes.interval(), 8);
+        assert_eq!(keyframes.before(7).unwrap().generation, 5);
+        assert_eq!(keyframes.before(4).unwrap().generation, 0);
+    }
+}
@@ -0,0 +1,30 @@
+// A GPU cellular automaton simulator, with a renderer to draw it and readers
+// and writers for the usual pattern formats. The viewer drives it all from a
+// window, and main.rs only wires winit's events up to it.
+pub mod apgcode;
+pub mod clipboard;
+pub mod generate;
+pub mod golly;
+pub mod history;
+pub mod life;
+pub mod load;
+pub mod macrocell;
+pub mod palette;
+pub mod pattern;
+pub mod render;
+pub mod rle;
+pub mod rule;
+pub mod selection;
+pub mod session;
+#[cfg(feature = "ui")]
+pub mod ui;
+pub mod viewer;
+
+pub use generate::{Error, Generator};
+pub use life::{Config, Life};
+pub use load::PatternFile;
+pub use pattern::Pattern;
+pub use render::Renderer;
+pub use rule::Rule;
+pub use session::Session;
+pub use viewer::Viewer;
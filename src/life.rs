@@ -4,43 +4,228 @@ use bytemuck::{Pod, Zeroable};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferAddress,
-    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor,
-    ComputePipeline, ComputePipelineDescriptor, Device, Queue, ShaderModuleDescriptor,
-    ShaderSource, TextureView,
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePass, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, Device, Maintain, MapMode, Queue, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, TextureView,
 };
 
-#[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
+use crate::{
+    golly::{GollyRule, Lookup},
+    history::{History, Keyframes, Snapshot},
+    rule::{Family, LargerThanLife, Rule, Shape},
+};
+
+// Written into life.wgsl in place of WORKGROUP_SIZE, so the dispatch always
+// matches the shader
+const WORKGROUP_SIZE: (u32, u32) = (5, 5);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Config {
     pub width: u32,
     pub height: u32,
+    pub rule: Rule,
+    // How many bytes of undo history to keep
+    pub history_limit: usize,
+    // How many generations apart to save keyframes for seeking back, and how
+    // many bytes of them to keep before spacing them further apart
+    pub keyframe_interval: u64,
+    pub keyframe_limit: usize,
+}
+
+impl Config {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            rule: Rule::life(),
+            history_limit: 256 << 20,
+            keyframe_interval: 100,
+            keyframe_limit: 256 << 20,
+        }
+    }
+}
+
+// Config as laid out in the compute shader's uniform buffer
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Params {
+    width: u32,
+    height: u32,
+    states: u32,
+    // Which cells of the 3x3 block the kernel samples
+    neighbourhood: u32,
+    // 0 for one colour, otherwise the number of colours
+    colours: u32,
+    // 1 for Generations rules, whose states from 2 up are dying cells
+    decays: u32,
+}
+
+impl From<&Config> for Params {
+    fn from(config: &Config) -> Self {
+        Self {
+            width: config.width,
+            height: config.height,
+            states: config.rule.states(),
+            neighbourhood: match &config.rule {
+                Rule::Isotropic(rule) => rule.neighbourhood.mask() as u32,
+                Rule::Golly(rule) => rule.neighbourhood.mask() as u32,
+                _ => 0,
+            },
+            colours: match &config.rule {
+                Rule::Isotropic(rule) if rule.family != Family::Single => {
+                    rule.family.colours().len() as u32
+                }
+                _ => 0,
+            },
+            decays: config.rule.decays() as u32,
+        }
+    }
+}
+
+// A Larger than Life rule as laid out in its kernel's uniform buffer
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LargerThanLifeParams {
+    radius: u32,
+    middle: u32,
+    survival_min: u32,
+    survival_max: u32,
+    birth_min: u32,
+    birth_max: u32,
+    shape: u32,
+}
+
+impl From<&LargerThanLife> for LargerThanLifeParams {
+    fn from(rule: &LargerThanLife) -> Self {
+        Self {
+            radius: rule.radius,
+            middle: rule.middle as u32,
+            survival_min: rule.survival.0,
+            survival_max: rule.survival.1,
+            birth_min: rule.birth.0,
+            birth_max: rule.birth.1,
+            shape: match rule.shape {
+                Shape::Moore => 0,
+                Shape::VonNeumann => 1,
+                Shape::Circular => 2,
+            },
+        }
+    }
+}
+
+// How the generic kernel evaluates a Golly rule, as laid out in its uniform
+// buffer
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GollyParams {
+    tree: u32,
+    inputs: u32,
+    words: u32,
+    root: u32,
+    // Where a table's outputs start in the lookup buffer, after its masks
+    outputs: u32,
+    _padding: [u32; 3],
+    // Where each input is read from, relative to the cell, in xy
+    offsets: [[i32; 4]; 9],
+}
+
+impl From<&GollyRule> for GollyParams {
+    fn from(rule: &GollyRule) -> Self {
+        let mut offsets = [[0; 4]; 9];
+        for (offset, &(dx, dy)) in offsets.iter_mut().zip(rule.offsets()) {
+            *offset = [dx, dy, 0, 0];
+        }
+        let (tree, root, outputs) = match &rule.lookup {
+            Lookup::Table { masks, .. } => (0, 0, masks.len() as u32),
+            Lookup::Tree { root, .. } => (1, *root, 0),
+        };
+        Self {
+            tree,
+            inputs: rule.offsets().len() as u32,
+            words: rule.words() as u32,
+            root,
+            outputs,
+            _padding: [0; 3],
+            offsets,
+        }
+    }
+}
+
+// One dispatch of a compute pipeline, run every step in order
+pub struct Pass {
+    pub pipeline: ComputePipeline,
+    pub bind_group: BindGroup,
+    pub workgroups: (u32, u32),
+}
+
+impl Pass {
+    pub fn new(
+        device: &Device,
+        module: &ShaderModule,
+        entry_point: &str,
+        resources: &[(u32, BindingResource)],
+        workgroups: (u32, u32),
+    ) -> Self {
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Compute shader pipeline"),
+            layout: None,
+            module,
+            entry_point,
+        });
+        let entries = resources
+            .iter()
+            .map(|(binding, resource)| BindGroupEntry {
+                binding: *binding,
+                resource: resource.clone(),
+            })
+            .collect::<Vec<_>>();
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Compute shader bind group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        Self {
+            pipeline,
+            bind_group,
+            workgroups,
+        }
+    }
+    pub fn dispatch<'a>(&'a self, compute_pass: &mut ComputePass<'a>) {
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.insert_debug_marker("Compute shader runtime");
+        compute_pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, 1);
+    }
 }
 
 pub struct Life {
     config: Config,
-    pipeline: ComputePipeline,
-    bind_group: BindGroup,
+    passes: Vec<Pass>,
+    // Bring the ages and texture up to date with a board that's been
+    // replaced, either by an edit or with another board altogether
+    refresh: Pass,
+    reset: Pass,
     input_buffer: Buffer,
     output_buffer: Buffer,
     data_size: BufferAddress,
+    generation: u64,
+    history: History,
+    keyframes: Keyframes,
 }
 
 impl Life {
     pub async fn new(
         device: &Device,
         texture_view: &TextureView,
-        config: Config,
+        config: &Config,
         data: Vec<u32>,
     ) -> Self {
+        let mut keyframes = Keyframes::new(config.keyframe_interval, config.keyframe_limit);
+        keyframes.insert(Snapshot::new(config, &data, 0));
         let data_slice_size = data.len() * std::mem::size_of::<u32>();
         let buffer_size = data_slice_size as BufferAddress;
 
         // Instantiate compute shader buffers
-        let compute_shader = Cow::Borrowed(include_str!("../shaders/life.wgsl"));
-        let compute_shader_module = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Compute shader module"),
-            source: ShaderSource::Wgsl(compute_shader),
-        });
         let input_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Input buffer"),
             contents: bytemuck::cast_slice(&data),
@@ -49,69 +234,198 @@ impl Life {
         let output_buffer = device.create_buffer(&BufferDescriptor {
             label: Some("Output buffer"),
             size: buffer_size,
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
         let config_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Config buffer"),
-            contents: bytemuck::bytes_of(&config),
+            contents: bytemuck::bytes_of(&Params::from(config)),
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
+        let shared = [
+            (0, config_buffer.as_entire_binding()),
+            (1, input_buffer.as_entire_binding()),
+            (2, output_buffer.as_entire_binding()),
+        ];
 
-        // Create compute shader bind group
-        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
-            label: Some("Compute shader pipeline"),
-            layout: None,
-            module: &compute_shader_module,
-            entry_point: "main",
+        // Create compute shader pipelines for the rule's kernel
+        let mut passes = match &config.rule {
+            Rule::Isotropic(rule) => {
+                let module = device.create_shader_module(ShaderModuleDescriptor {
+                    label: Some("Compute shader module"),
+                    source: ShaderSource::Wgsl(Cow::Owned(
+                        include_str!("../shaders/life.wgsl").replace(
+                            "WORKGROUP_SIZE",
+                            &format!("{}, {}", WORKGROUP_SIZE.0, WORKGROUP_SIZE.1),
+                        ),
+                    )),
+                });
+                let table = (0..512)
+                    .map(|neighbourhood| rule.next(neighbourhood) as u32)
+                    .collect::<Vec<_>>();
+                let table_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Rule table buffer"),
+                    contents: bytemuck::cast_slice(&table),
+                    usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                });
+                let mut resources = shared.to_vec();
+                resources.push((4, table_buffer.as_entire_binding()));
+                vec![Pass::new(
+                    device,
+                    &module,
+                    "main",
+                    &resources,
+                    (
+                        config.width.div_ceil(WORKGROUP_SIZE.0),
+                        config.height.div_ceil(WORKGROUP_SIZE.1),
+                    ),
+                )]
+            }
+            Rule::LargerThanLife(rule) => {
+                let module = device.create_shader_module(ShaderModuleDescriptor {
+                    label: Some("Larger than Life shader module"),
+                    source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("../shaders/ltl.wgsl"))),
+                });
+                let rule_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Larger than Life rule buffer"),
+                    contents: bytemuck::bytes_of(&LargerThanLifeParams::from(rule)),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                });
+                let totals_buffer = device.create_buffer(&BufferDescriptor {
+                    label: Some("Summed area table buffer"),
+                    size: ((config.width + 1) * (config.height + 1)) as BufferAddress
+                        * std::mem::size_of::<u32>() as BufferAddress,
+                    usage: BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                });
+                let row_sums = Pass::new(
+                    device,
+                    &module,
+                    "row_sums",
+                    &[
+                        (0, config_buffer.as_entire_binding()),
+                        (1, input_buffer.as_entire_binding()),
+                        (5, totals_buffer.as_entire_binding()),
+                    ],
+                    (config.height.div_ceil(64), 1),
+                );
+                let column_sums = Pass::new(
+                    device,
+                    &module,
+                    "column_sums",
+                    &[
+                        (0, config_buffer.as_entire_binding()),
+                        (5, totals_buffer.as_entire_binding()),
+                    ],
+                    ((config.width + 1).div_ceil(64), 1),
+                );
+                let mut resources = shared.to_vec();
+                resources.push((4, rule_buffer.as_entire_binding()));
+                resources.push((5, totals_buffer.as_entire_binding()));
+                let main = Pass::new(
+                    device,
+                    &module,
+                    "main",
+                    &resources,
+                    (config.width.div_ceil(8), config.height.div_ceil(8)),
+                );
+                vec![row_sums, column_sums, main]
+            }
+            Rule::Golly(rule) => {
+                let module = device.create_shader_module(ShaderModuleDescriptor {
+                    label: Some("Golly rule shader module"),
+                    source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                        "../shaders/golly.wgsl"
+                    ))),
+                });
+                let rule_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Golly rule buffer"),
+                    contents: bytemuck::bytes_of(&GollyParams::from(rule)),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                });
+                let mut lookup = match &rule.lookup {
+                    Lookup::Table { masks, outputs } => [&masks[..], &outputs[..]].concat(),
+                    Lookup::Tree { nodes, .. } => nodes.clone(),
+                };
+                // Storage buffers can't be empty, even for a table with no
+                // transitions
+                lookup.push(0);
+                let lookup_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Golly lookup buffer"),
+                    contents: bytemuck::cast_slice(&lookup),
+                    usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                });
+                let mut resources = shared.to_vec();
+                resources.push((4, rule_buffer.as_entire_binding()));
+                resources.push((5, lookup_buffer.as_entire_binding()));
+                vec![Pass::new(
+                    device,
+                    &module,
+                    "main",
+                    &resources,
+                    (config.width.div_ceil(8), config.height.div_ceil(8)),
+                )]
+            }
+        };
+
+        // Track each cell's age after every step, and draw to the texture
+        let ages = data
+            .iter()
+            .flat_map(|&state| match config.rule.alive(state) {
+                true => [1, 0],
+                false => [0, u32::MAX],
+            })
+            .collect::<Vec<_>>();
+        let ages_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Ages buffer"),
+            contents: bytemuck::cast_slice(&ages),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
         });
-        let compute_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Compute shader bind group"),
-            layout: &compute_pipeline.get_bind_group_layout(0),
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: config_buffer.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: input_buffer.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: output_buffer.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 3,
-                    resource: BindingResource::TextureView(&texture_view),
-                },
-            ],
+        let age_module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Age shader module"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("../shaders/age.wgsl"))),
+        });
+        let [ages, refresh, reset] = ["main", "refresh", "reset"].map(|entry_point| {
+            Pass::new(
+                device,
+                &age_module,
+                entry_point,
+                &[
+                    (0, config_buffer.as_entire_binding()),
+                    (2, output_buffer.as_entire_binding()),
+                    (3, BindingResource::TextureView(texture_view)),
+                    (4, ages_buffer.as_entire_binding()),
+                ],
+                (config.width.div_ceil(8), config.height.div_ceil(8)),
+            )
         });
+        passes.push(ages);
 
         Self {
-            config,
+            config: config.clone(),
+            passes,
+            refresh,
+            reset,
             input_buffer,
             output_buffer,
             data_size: buffer_size,
-            bind_group: compute_bind_group,
-            pipeline: compute_pipeline,
+            generation: 0,
+            history: History::new(config.history_limit),
+            keyframes,
         }
     }
     pub async fn step(&mut self, device: &Device, queue: &Queue) {
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
             label: Some("Compute shader command encoder"),
         });
-        let x_groups = self.config.width / crate::WORKGROUP_SIZE.0;
-        let y_groups = self.config.height / crate::WORKGROUP_SIZE.1;
 
         // Simulate a single step of life
         let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
             label: Some("Compute shader pass"),
         });
-        compute_pass.set_pipeline(&self.pipeline);
-        compute_pass.set_bind_group(0, &self.bind_group, &[]);
-        compute_pass.insert_debug_marker("Compute shader runtime");
-        compute_pass.dispatch_workgroups(x_groups, y_groups, 1);
+        for pass in &self.passes {
+            pass.dispatch(&mut compute_pass);
+        }
         drop(compute_pass);
 
         // Copies data from the output buffer to the input buffer
@@ -125,5 +439,173 @@ impl Life {
 
         // Dispatch commands to be executed
         queue.submit(Some(encoder.finish()));
+        self.generation += 1;
+
+        // Replaying from before an edit takes it up again when it's reached
+        if let Some(edit) = self.keyframes.edited(self.generation) {
+            let data = edit.data();
+            self.upload(device, queue, &data, &self.refresh);
+        } else if self.keyframes.due(self.generation) {
+            let data = self.read(device, queue).await;
+            self.keyframes
+                .insert(Snapshot::new(&self.config, &data, self.generation));
+        }
+    }
+    // Copies the current board back from the GPU
+    pub async fn read(&self, device: &Device, queue: &Queue) -> Vec<u32> {
+        let staging_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Staging buffer"),
+            size: self.data_size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Read back command encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.input_buffer, 0, &staging_buffer, 0, self.data_size);
+        queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        buffer_slice.map_async(MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+        device.poll(Maintain::Wait);
+        receiver.receive().await.unwrap().unwrap();
+
+        let data = bytemuck::cast_slice(&buffer_slice.get_mapped_range()).to_vec();
+        staging_buffer.unmap();
+        data
+    }
+    // Replaces the whole board, as from an edited copy of read, to be
+    // stepped from next. Later keyframes no longer follow from it, so are
+    // dropped.
+    pub fn write(&mut self, device: &Device, queue: &Queue, data: &[u32]) {
+        self.keyframes
+            .edit(Snapshot::new(&self.config, data, self.generation));
+        self.upload(device, queue, data, &self.refresh);
+    }
+    // Runs one of the age passes that leave the ages alone, so the texture
+    // shows the new board even while paused
+    fn upload(&self, device: &Device, queue: &Queue, data: &[u32], ages: &Pass) {
+        queue.write_buffer(&self.input_buffer, 0, bytemuck::cast_slice(data));
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Write command encoder"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.input_buffer,
+            0,
+            &self.output_buffer,
+            0,
+            self.data_size,
+        );
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Age pass"),
+        });
+        ages.dispatch(&mut compute_pass);
+        drop(compute_pass);
+        queue.submit(Some(encoder.finish()));
+    }
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+    // Takes over the undo history of the Life this one replaces, so that
+    // changes of size or rule can be undone too
+    pub fn inherit_history(&mut self, old: &mut Life) {
+        self.history = std::mem::replace(&mut old.history, History::new(0));
+    }
+    // Remembers the board before an edit or run, given as from read, so that
+    // it can be undone
+    pub fn record(&mut self, data: &[u32]) {
+        self.history
+            .record(Snapshot::new(&self.config, data, self.generation));
+    }
+    pub async fn checkpoint(&mut self, device: &Device, queue: &Queue) {
+        let data = self.read(device, queue).await;
+        self.record(&data);
+    }
+    // Goes back to before the last edit or run, returning the board from
+    // then. Boards of a different size or rule can't be written into this
+    // one, so it's up to the caller to replace it with one made from them.
+    pub async fn undo(&mut self, device: &Device, queue: &Queue) -> Option<Snapshot> {
+        let current = Snapshot::new(
+            &self.config,
+            &self.read(device, queue).await,
+            self.generation,
+        );
+        let snapshot = self.history.undo(current)?;
+        self.restore(device, queue, &snapshot);
+        Some(snapshot)
+    }
+    pub async fn redo(&mut self, device: &Device, queue: &Queue) -> Option<Snapshot> {
+        let current = Snapshot::new(
+            &self.config,
+            &self.read(device, queue).await,
+            self.generation,
+        );
+        let snapshot = self.history.redo(current)?;
+        self.restore(device, queue, &snapshot);
+        Some(snapshot)
+    }
+    // Puts the board back as it was, if it has this board's size and rule
+    pub fn restore(&mut self, device: &Device, queue: &Queue, snapshot: &Snapshot) {
+        if snapshot.config == self.config {
+            self.generation = snapshot.generation;
+            self.replace(device, queue, &snapshot.data());
+        }
+    }
+    // Carries on from a saved board at a later generation, with nothing to
+    // undo or seek back to from before it
+    pub fn resume(&mut self, device: &Device, queue: &Queue, generation: u64, data: &[u32]) {
+        self.history.clear();
+        self.keyframes.clear();
+        self.generation = generation;
+        self.replace(device, queue, data);
+    }
+    // Like write, but for a board that doesn't follow from the last one, so
+    // cell ages start again from it, as snapshots don't keep them
+    fn replace(&mut self, device: &Device, queue: &Queue, data: &[u32]) {
+        self.keyframes
+            .edit(Snapshot::new(&self.config, data, self.generation));
+        self.upload(device, queue, data, &self.reset);
+    }
+    // Goes to any generation since the last edit, earlier ones by stepping
+    // forward from the keyframe before them, returning whether it could.
+    // Seeking isn't recorded in the undo history, and cell ages count from
+    // the keyframe.
+    pub async fn seek(&mut self, device: &Device, queue: &Queue, generation: u64) -> bool {
+        let keyframe = self
+            .keyframes
+            .before(generation)
+            .filter(|keyframe| {
+                generation < self.generation || keyframe.generation > self.generation
+            })
+            .map(|keyframe| (keyframe.generation, keyframe.data()));
+        match keyframe {
+            Some((start, data)) => {
+                self.upload(device, queue, &data, &self.reset);
+                self.generation = start;
+            }
+            None if generation < self.generation => return false,
+            None => {}
+        }
+        while self.generation < generation {
+            self.step(device, queue).await;
+        }
+        true
+    }
+}
+
+// The number of cells in each state, indexed by state
+pub fn populations(data: &[u32], states: u32) -> Vec<usize> {
+    let mut populations = vec![0; states as usize];
+    for &state in data {
+        if let Some(population) = populations.get_mut(state as usize) {
+            *population += 1;
+        }
     }
+    populations
 }
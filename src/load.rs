@@ -0,0 +1,238 @@
+use std::{fs, path::Path};
+
+use crate::{
+    generate::{self, Error},
+    life::Config,
+    macrocell,
+    pattern::Pattern,
+    rle,
+    rule::Rule,
+};
+
+// Wider than any board a GPU can hold, so macrocell files larger than this are
+// turned away before they're expanded
+const LARGEST: i64 = 1 << 15;
+
+// A pattern read from a file of any supported format, with the rule the file
+// asks for if it says
+pub struct PatternFile {
+    pub pattern: Pattern,
+    pub rule: Option<String>,
+}
+
+// Picks the format from the extension: .cells, .rle, .lif (Life 1.05 or 1.06,
+// told apart by the header) or .mc
+pub fn read(path: impl AsRef<Path>) -> Result<PatternFile, Error> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let text = fs::read_to_string(path)?;
+    match extension.as_str() {
+        "cells" => Ok(PatternFile {
+            pattern: generate::parse_plaintext(&text)?,
+            rule: None,
+        }),
+        "rle" => {
+            let file = rle::parse(&text)?;
+            Ok(PatternFile {
+                pattern: file.pattern,
+                rule: file.rule,
+            })
+        }
+        "lif" | "life" if text.trim_start().starts_with("#Life 1.06") => Ok(PatternFile {
+            pattern: generate::parse_life106(&text)?.into_iter().collect(),
+            rule: None,
+        }),
+        "lif" | "life" => {
+            let file = generate::parse_life105(&text)?;
+            Ok(PatternFile {
+                pattern: file.positions.into_iter().collect(),
+                rule: file.rule,
+            })
+        }
+        "mc" => {
+            let file = macrocell::parse(&text)?;
+            let bounds = file.bounding_box().unwrap_or_default();
+            let (min_x, min_y, max_x, max_y) = bounds;
+            if max_x - min_x >= LARGEST || max_y - min_y >= LARGEST {
+                return Err(Error::Invalid(format!(
+                    "{} is too large to put on a board",
+                    path.display()
+                )));
+            }
+            Ok(PatternFile {
+                pattern: file.pattern(bounds),
+                rule: file.rule,
+            })
+        }
+        _ => Err(Error::Invalid(format!(
+            "{} isn't a pattern file this can read",
+            path.display()
+        ))),
+    }
+}
+
+// The board with the pattern in the middle, either on its own with the file's
+// rule or on top of the old board, growing it to fit up to `limit` cells
+// across. Whatever's already there stays centred as the board grows.
+pub fn place(
+    file: &PatternFile,
+    config: &Config,
+    old: Option<&[u32]>,
+    limit: u32,
+) -> Result<(Config, Vec<u32>), Error> {
+    let pattern = file.pattern.normalise();
+    let (width, height) = match pattern.bounding_box() {
+        Some((_, _, max_x, max_y)) => (max_x as u32 + 1, max_y as u32 + 1),
+        None => (0, 0),
+    };
+
+    let mut new = config.clone();
+    match file.rule.as_deref().map(str::parse::<Rule>) {
+        Some(Ok(rule)) if old.is_none() => new.rule = rule,
+        Some(Ok(rule)) if rule == new.rule => {}
+        Some(_) => log::warn!(
+            "Loading a pattern for {} into {}",
+            file.rule.as_deref().unwrap_or_default(),
+            new.rule
+        ),
+        None => {}
+    }
+    // Room for the pattern to grow on every side
+    if width > new.width || height > new.height {
+        new.width = new.width.max(width.saturating_mul(2));
+        new.height = new.height.max(height.saturating_mul(2));
+        if new.width > limit || new.height > limit {
+            return Err(Error::Invalid(format!(
+                "a {width}x{height} pattern doesn't fit on the largest possible board"
+            )));
+        }
+    }
+
+    let mut data = generate::empty_board(&new);
+    if let Some(old) = old {
+        let x_offset = (new.width - config.width) / 2;
+        let y_offset = (new.height - config.height) / 2;
+        for (index, &state) in old.iter().enumerate() {
+            let x = index as u32 % config.width + x_offset;
+            let y = index as u32 / config.width + y_offset;
+            data[(y * new.width + x) as usize] = state;
+        }
+    }
+    let x_offset = (new.width - width) / 2;
+    let y_offset = (new.height - height) / 2;
+    for (x, y, state) in pattern.cells() {
+        data[((y as u32 + y_offset) * new.width + x as u32 + x_offset) as usize] = state;
+    }
+    Ok((new, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fmt::Write, path::PathBuf};
+
+    use super::*;
+
+    // A file in the temporary directory that's removed again after the test
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("libconway-{}-{name}", std::process::id()));
+            fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn glider(rule: Option<&str>) -> PatternFile {
+        PatternFile {
+            pattern: Pattern::glider(),
+            rule: rule.map(String::from),
+        }
+    }
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        for (name, contents, rule) in [
+            ("glider.cells", "!Name: Glider\n.O\n..O\nOOO\n", None),
+            (
+                "glider.RLE",
+                "x = 3, y = 3, rule = B36/S23\nbo$2bo$3o!\n",
+                Some("B36/S23"),
+            ),
+            ("glider.lif", "#Life 1.06\n1 0\n2 1\n0 2\n1 2\n2 2\n", None),
+            (
+                "glider.life",
+                "#Life 1.05\n#R 23/36\n#P 0 0\n.*\n..*\n***\n",
+                Some("B36/S23"),
+            ),
+            (
+                "glider.mc",
+                "[M2] (libconway)\n#R B3/S23\n.*$..*$***$\n",
+                Some("B3/S23"),
+            ),
+        ] {
+            let file = TempFile::new(name, contents);
+            let read = read(&file.0).unwrap();
+            assert_eq!(read.pattern.normalise(), Pattern::glider(), "{name}");
+            assert_eq!(read.rule.as_deref(), rule, "{name}");
+        }
+        let file = TempFile::new("glider.txt", "bo$2bo$3o!\n");
+        assert!(matches!(read(&file.0), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn turns_away_macrocell_files_too_large_for_a_board() {
+        // A cell in two opposite corners of a level 17 node, 2^17 cells across
+        let mut text = String::from("[M2] (libconway)\n*$\n");
+        for level in 4..=17 {
+            let child = level - 3;
+            writeln!(text, "{level} {child} 0 0 {child}").unwrap();
+        }
+        let file = TempFile::new("large.mc", &text);
+        assert!(matches!(read(&file.0), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn takes_the_files_rule_only_on_an_empty_board() {
+        let config = Config::new(8, 8);
+        let (loaded, _) = place(&glider(Some("B36/S23")), &config, None, 64).unwrap();
+        assert_eq!(loaded.rule.to_string(), "B36/S23");
+        let old = vec![0; 64];
+        let (added, _) = place(&glider(Some("B36/S23")), &config, Some(&old), 64).unwrap();
+        assert_eq!(added.rule, config.rule);
+    }
+
+    #[test]
+    fn grows_the_board_around_whats_there() {
+        let config = Config::new(4, 4);
+        let mut old = vec![0; 16];
+        old[0] = 1;
+        let file = PatternFile {
+            pattern: (0..6).map(|x| (x, 0)).collect(),
+            rule: None,
+        };
+        let (grown, data) = place(&file, &config, Some(&old), 64).unwrap();
+        assert_eq!((grown.width, grown.height), (12, 4));
+        // The old board's corner moves to the middle of the new one, and the
+        // row of six lands in the middle too
+        assert_eq!(data[4], 1);
+        assert_eq!(data[12 + 3..12 + 9], [1; 6]);
+        assert_eq!(data.iter().sum::<u32>(), 7);
+
+        assert!(matches!(
+            place(&file, &config, Some(&old), 8),
+            Err(Error::Invalid(_))
+        ));
+    }
+}
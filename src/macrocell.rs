@@ -0,0 +1,452 @@
+use std::{collections::HashMap, fmt::Write, fs};
+
+use crate::{
+    generate::{self, Error, Generator},
+    pattern::{Pattern, Scene},
+};
+
+enum Node {
+    // A level 3 (8x8) leaf of a two state file, as live (x, y) positions
+    Leaf(Vec<(u8, u8)>),
+    // A level 1 (2x2) node of a multi-state file, holding states directly
+    States([u32; 4]),
+    Branch { level: u32, children: [usize; 4] },
+}
+
+impl Node {
+    fn level(&self) -> u32 {
+        match self {
+            Node::Leaf(_) => 3,
+            Node::States(_) => 1,
+            Node::Branch { level, .. } => *level,
+        }
+    }
+}
+
+// The quadtree is kept as it is in the file, since shared subtrees can stand
+// for far more cells than would fit in memory; `pattern` only expands the part
+// that's wanted
+pub struct MacrocellFile {
+    pub rule: Option<String>,
+    pub generation: Option<String>,
+    nodes: Vec<Option<Node>>,
+}
+
+pub fn parse(text: &str) -> Result<MacrocellFile, Error> {
+    let mut rule = None;
+    let mut generation = None;
+    // Node 0 is the empty node, so real nodes are numbered from 1
+    let mut nodes: Vec<Option<Node>> = vec![None];
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let bad_line = |message: String| Error::Parse {
+            line: line_number + 1,
+            message,
+        };
+        if line.is_empty() || line.starts_with('[') {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(value) = comment.strip_prefix('R') {
+                rule = Some(value.trim().to_string());
+            } else if let Some(value) = comment.strip_prefix('G') {
+                generation = Some(value.trim().to_string());
+            }
+            continue;
+        }
+        if line.starts_with(['.', '*', '$']) {
+            let (mut x, mut y) = (0u8, 0u8);
+            let mut cells = vec![];
+            for chr in line.chars() {
+                match chr {
+                    '.' => x += 1,
+                    '*' => {
+                        cells.push((x, y));
+                        x += 1;
+                    }
+                    '$' => {
+                        x = 0;
+                        y += 1;
+                    }
+                    _ => return Err(bad_line(format!("unexpected character {chr:?}"))),
+                }
+                if x > 8 || y > 8 {
+                    return Err(bad_line("leaf is larger than 8x8".into()));
+                }
+            }
+            nodes.push(Some(Node::Leaf(cells)));
+            continue;
+        }
+
+        let numbers = line
+            .split_whitespace()
+            .map(|n| n.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| bad_line(err.to_string()))?;
+        let [level, nw, ne, sw, se] = numbers[..] else {
+            return Err(bad_line(format!("expected 5 numbers, found {line:?}")));
+        };
+        if level == 1 {
+            nodes.push(Some(Node::States([nw, ne, sw, se].map(|s| s as u32))));
+            continue;
+        }
+        // Centred on the origin, a level 32 node just fits in i32 coordinates
+        if !(2..=32).contains(&level) {
+            return Err(bad_line(format!("unsupported node level {level}")));
+        }
+        let children = [nw, ne, sw, se];
+        for &child in &children {
+            match nodes.get(child) {
+                Some(None) => {}
+                Some(Some(node)) if node.level() + 1 == level as u32 => {}
+                _ => return Err(bad_line(format!("bad child node {child}"))),
+            }
+        }
+        nodes.push(Some(Node::Branch {
+            level: level as u32,
+            children,
+        }));
+    }
+
+    Ok(MacrocellFile {
+        rule,
+        generation,
+        nodes,
+    })
+}
+
+// (min_x, min_y, max_x, max_y), inclusive
+type Bounds = (i64, i64, i64, i64);
+
+impl MacrocellFile {
+    // Golly centres the root node on the origin
+    fn root(&self) -> Option<(usize, i64)> {
+        let root = self.nodes.last()?.as_ref()?;
+        Some((self.nodes.len() - 1, -(1i64 << (root.level() - 1))))
+    }
+
+    // The live cells' bounding box, found without expanding anything by
+    // working out each node's once from its children's
+    pub fn bounding_box(&self) -> Option<Bounds> {
+        let mut boxes: Vec<Option<Bounds>> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let bounds = match node {
+                None => None,
+                Some(Node::Leaf(cells)) => cells.iter().fold(None, |bounds, &(x, y)| {
+                    Some(union(bounds, (x as i64, y as i64, x as i64, y as i64)))
+                }),
+                Some(Node::States(states)) => {
+                    (0..4).filter(|&i| states[i] != 0).fold(None, |bounds, i| {
+                        let (x, y) = (i as i64 % 2, i as i64 / 2);
+                        Some(union(bounds, (x, y, x, y)))
+                    })
+                }
+                Some(Node::Branch { level, children }) => {
+                    let half = 1i64 << (level - 1);
+                    let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+                    let mut bounds = None;
+                    for (&child, (dx, dy)) in children.iter().zip(offsets) {
+                        if let Some((min_x, min_y, max_x, max_y)) = boxes[child] {
+                            let child_bounds = (min_x + dx, min_y + dy, max_x + dx, max_y + dy);
+                            bounds = Some(union(bounds, child_bounds));
+                        }
+                    }
+                    bounds
+                }
+            };
+            boxes.push(bounds);
+        }
+        let (root, corner) = self.root()?;
+        let (min_x, min_y, max_x, max_y) = boxes[root]?;
+        Some((
+            min_x + corner,
+            min_y + corner,
+            max_x + corner,
+            max_y + corner,
+        ))
+    }
+
+    // The cells within bounds, skipping every node that lies outside them
+    pub fn pattern(&self, bounds: Bounds) -> Pattern {
+        let mut pattern = Pattern::new();
+        if let Some((root, corner)) = self.root() {
+            expand(&self.nodes, root, corner, corner, bounds, &mut pattern);
+        }
+        pattern
+    }
+}
+
+fn union(bounds: Option<Bounds>, (min_x, min_y, max_x, max_y): Bounds) -> Bounds {
+    match bounds {
+        Some((x0, y0, x1, y1)) => (x0.min(min_x), y0.min(min_y), x1.max(max_x), y1.max(max_y)),
+        None => (min_x, min_y, max_x, max_y),
+    }
+}
+
+fn expand(
+    nodes: &[Option<Node>],
+    index: usize,
+    x: i64,
+    y: i64,
+    bounds: Bounds,
+    pattern: &mut Pattern,
+) {
+    let Some(node) = &nodes[index] else {
+        return;
+    };
+    let (min_x, min_y, max_x, max_y) = bounds;
+    let size = 1i64 << node.level();
+    if x > max_x || y > max_y || x + size <= min_x || y + size <= min_y {
+        return;
+    }
+    // The root's level is limited so that every cell fits in i32
+    let mut set = |x: i64, y: i64, state: u32| {
+        if (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y) {
+            pattern.set(x as i32, y as i32, state);
+        }
+    };
+    match node {
+        Node::Leaf(cells) => {
+            for &(cx, cy) in cells {
+                set(x + cx as i64, y + cy as i64, 1);
+            }
+        }
+        Node::States([nw, ne, sw, se]) => {
+            set(x, y, *nw);
+            set(x + 1, y, *ne);
+            set(x, y + 1, *sw);
+            set(x + 1, y + 1, *se);
+        }
+        Node::Branch { level, children } => {
+            let half = 1i64 << (level - 1);
+            let [nw, ne, sw, se] = *children;
+            expand(nodes, nw, x, y, bounds, pattern);
+            expand(nodes, ne, x + half, y, bounds, pattern);
+            expand(nodes, sw, x, y + half, bounds, pattern);
+            expand(nodes, se, x + half, y + half, bounds, pattern);
+        }
+    }
+}
+
+pub struct Macrocell {
+    pub source: &'static str,
+    pub x_offset: u32,
+    pub y_offset: u32,
+}
+
+impl Macrocell {
+    pub fn read(&self) -> Result<MacrocellFile, Error> {
+        parse(&fs::read_to_string(self.source)?)
+    }
+}
+
+// Places the pattern's bounding box at the offset, and only expands the part
+// of it that lands on the board
+impl Generator for Macrocell {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let file = self.read()?;
+        let Some((min_x, min_y, max_x, max_y)) = file.bounding_box() else {
+            return Ok(generate::empty_board(config));
+        };
+        let (x_offset, y_offset) = (self.x_offset as i64, self.y_offset as i64);
+        let (right, bottom) = (max_x - min_x + x_offset, max_y - min_y + y_offset);
+        if right >= config.width as i64 || bottom >= config.height as i64 {
+            return Err(Error::OutOfBounds {
+                x: right,
+                y: bottom,
+            });
+        }
+        Scene {
+            patterns: vec![(
+                file.pattern((min_x, min_y, max_x, max_y)).normalise(),
+                self.x_offset as i32,
+                self.y_offset as i32,
+            )],
+        }
+        .generate(config)
+    }
+}
+
+// Hash-conses the board into a quadtree, writing each distinct node once
+struct Writer<'a> {
+    data: &'a [u32],
+    config: &'a crate::life::Config,
+    multi_state: bool,
+    ids: HashMap<String, usize>,
+    out: String,
+}
+
+impl Writer<'_> {
+    fn cell(&self, x: u32, y: u32) -> u32 {
+        if x < self.config.width && y < self.config.height {
+            self.data[(y * self.config.width + x) as usize]
+        } else {
+            0
+        }
+    }
+
+    fn node(&mut self, line: String) -> usize {
+        let next_id = self.ids.len() + 1;
+        let id = *self.ids.entry(line.clone()).or_insert(next_id);
+        if id == next_id {
+            writeln!(self.out, "{line}").unwrap();
+        }
+        id
+    }
+
+    fn build(&mut self, x: u32, y: u32, level: u32) -> usize {
+        if x >= self.config.width || y >= self.config.height {
+            return 0;
+        }
+        if self.multi_state && level == 1 {
+            let states = [
+                self.cell(x, y),
+                self.cell(x + 1, y),
+                self.cell(x, y + 1),
+                self.cell(x + 1, y + 1),
+            ];
+            if states == [0; 4] {
+                return 0;
+            }
+            let [nw, ne, sw, se] = states;
+            return self.node(format!("1 {nw} {ne} {sw} {se}"));
+        }
+        if !self.multi_state && level == 3 {
+            let rows = (0..8)
+                .map(|dy| {
+                    let row = (0..8)
+                        .map(|dx| {
+                            if self.cell(x + dx, y + dy) != 0 {
+                                '*'
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect::<String>();
+                    row.trim_end_matches('.').to_string()
+                })
+                .collect::<Vec<_>>();
+            let leaf = rows.join("$");
+            let leaf = leaf.trim_end_matches('$');
+            if leaf.is_empty() {
+                return 0;
+            }
+            return self.node(format!("{leaf}$"));
+        }
+        let half = 1 << (level - 1);
+        let children = [
+            self.build(x, y, level - 1),
+            self.build(x + half, y, level - 1),
+            self.build(x, y + half, level - 1),
+            self.build(x + half, y + half, level - 1),
+        ];
+        if children == [0; 4] {
+            return 0;
+        }
+        let [nw, ne, sw, se] = children;
+        self.node(format!("{level} {nw} {ne} {sw} {se}"))
+    }
+}
+
+pub fn write(data: &[u32], config: &crate::life::Config, rule: Option<&str>) -> String {
+    let multi_state = data.iter().any(|&state| state > 1);
+    let size = config.width.max(config.height).max(8);
+    let level = u32::BITS - (size - 1).leading_zeros();
+
+    let mut writer = Writer {
+        data,
+        config,
+        multi_state,
+        ids: HashMap::new(),
+        out: String::from("[M2] (libconway)\n"),
+    };
+    if let Some(rule) = rule {
+        writeln!(writer.out, "#R {rule}").unwrap();
+    }
+    if writer.build(0, 0, level) == 0 {
+        // An empty pattern still needs a root node
+        writer.node(format!("{level} 0 0 0 0"));
+    }
+    writer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::Config;
+
+    #[test]
+    fn round_trips_a_glider() {
+        let config = Config::new(16, 16);
+        let data = Scene {
+            patterns: vec![(Pattern::glider(), 5, 7)],
+        }
+        .generate(&config)
+        .unwrap();
+        let file = parse(&write(&data, &config, Some("B3/S23"))).unwrap();
+        assert_eq!(file.rule.as_deref(), Some("B3/S23"));
+        // The 16x16 root is centred on the origin
+        let bounds = file.bounding_box().unwrap();
+        assert_eq!(bounds, (-3, -1, -1, 1));
+        assert_eq!(file.pattern(bounds).normalise(), Pattern::glider());
+    }
+
+    #[test]
+    fn round_trips_multiple_states() {
+        let config = Config::new(4, 4);
+        let mut data = vec![0; 16];
+        data[1] = 2;
+        data[6] = 1;
+        data[15] = 3;
+        let file = parse(&write(&data, &config, None)).unwrap();
+        let pattern = file.pattern(file.bounding_box().unwrap());
+        // Boards are written as at least 8x8, centred on the origin
+        assert_eq!(pattern.population(), 3);
+        assert_eq!(pattern.get(-3, -4), 2);
+        assert_eq!(pattern.get(-2, -3), 1);
+        assert_eq!(pattern.get(-1, -1), 3);
+    }
+
+    // A cell at the corner of every quadrant down the diagonal, doubling at
+    // each of 29 levels, stands for 2^29 cells in 30 lines
+    fn diagonal() -> String {
+        let mut text = String::from("[M2] (libconway)\n*$\n");
+        for level in 4..=32 {
+            let child = level - 3;
+            writeln!(text, "{level} {child} 0 0 {child}").unwrap();
+        }
+        text
+    }
+
+    #[test]
+    fn measures_shared_trees_without_expanding_them() {
+        let file = parse(&diagonal()).unwrap();
+        let (low, high) = (-(1 << 31), (1 << 31) - 8);
+        assert_eq!(file.bounding_box(), Some((low, low, high, high)));
+    }
+
+    #[test]
+    fn only_expands_the_part_thats_wanted() {
+        let file = parse(&diagonal()).unwrap();
+        let low = -(1 << 31);
+        let pattern = file.pattern((low, low, low + 63, low + 63));
+        // Every combination of the 8, 16 and 32 offsets
+        assert_eq!(pattern.population(), 8);
+        for (x, y, state) in pattern.cells() {
+            assert_eq!((x, state), (y, 1));
+            assert_eq!((x as i64 - low) % 8, 0);
+        }
+    }
+
+    #[test]
+    fn rejects_nodes_too_large_or_mismatched() {
+        let mut text = diagonal();
+        text.push_str("33 32 0 0 0\n");
+        assert!(matches!(parse(&text), Err(Error::Parse { line: 32, .. })));
+        assert!(matches!(
+            parse("*$\n5 1 0 0 0\n"),
+            Err(Error::Parse { line: 2, .. })
+        ));
+    }
+}
@@ -1,190 +1,365 @@
+use std::path::PathBuf;
+#[cfg(feature = "ui")]
 use std::time::Instant;
 
-use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
 use winit::{
     dpi::PhysicalSize,
-    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event::{
+        ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+        VirtualKeyCode, WindowEvent,
+    },
     event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
 };
 
-mod generate;
-mod life;
-mod render;
-
-use generate::Generator;
+#[cfg(feature = "ui")]
+use libconway::ui;
+use libconway::{generate, golly, life, rule, selection::Clip, Generator, Viewer};
 
 const WIDTH: u32 = 1920;
 const HEIGHT: u32 = 1080;
-const WORKGROUP_SIZE: (u32, u32) = (5, 5);
 
 struct State {
     window: Window,
-    surface: Surface,
-    window_config: SurfaceConfiguration,
-    window_size: PhysicalSize<u32>,
-    device: Device,
-    queue: Queue,
-    life: life::Life,
-    renderer: render::Renderer,
+    viewer: Viewer,
+    modifiers: ModifiersState,
+    #[cfg(feature = "ui")]
+    overlay: ui::Overlay,
+    // Counted every so often while the panel is showing, when it was last
+    #[cfg(feature = "ui")]
+    population: (Instant, Option<usize>),
 }
 
 impl State {
     async fn new(window: Window, data: Vec<u32>, config: life::Config) -> Self {
-        // GPU INITIALISATION
         let instance = wgpu::Instance::default();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
-
-        // SETTING UNIVERSAL WINDOW STUFF (no move)
-        let window_size = window.inner_size();
         let surface = unsafe { instance.create_surface(&window) }.unwrap();
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .filter(|f| f.describe().srgb)
-            .next()
-            .unwrap_or(surface_caps.formats[0]);
-        let window_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: window_size.width,
-            height: window_size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
-        surface.configure(&device, &window_config);
-
-        // SHARED BETWEEN LIFE AND RENDERER HAS TO BE HERE
-        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: config.width,
-                height: config.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
-            format: wgpu::TextureFormat::Rgba32Float,
-            view_formats: &[],
-        });
-        let output_texture_view =
-            output_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        // INIT COMPUTE SHADER
-        let life = life::Life::new(&device, &output_texture_view, config, data).await;
-
-        let renderer = render::Renderer::new(&device, &window_config, &output_texture_view);
-
+        let size = window.inner_size();
+        let viewer = Viewer::new(&instance, surface, size.width, size.height, config, data).await;
+        #[cfg(feature = "ui")]
+        let overlay = ui::Overlay::new(
+            viewer.device(),
+            viewer.surface_format(),
+            &window,
+            ui::Panel::new("patterns", viewer.session()),
+        );
         Self {
             window,
-            surface,
-            window_config,
-            window_size,
-            device,
-            queue,
-            life,
-            renderer,
+            viewer,
+            modifiers: ModifiersState::empty(),
+            #[cfg(feature = "ui")]
+            overlay,
+            #[cfg(feature = "ui")]
+            population: (Instant::now(), None),
         }
     }
     fn window(&self) -> &Window {
         &self.window
     }
-    fn update(&mut self) {
-        let start = Instant::now();
-        pollster::block_on(self.life.step(&self.device, &self.queue));
-        let elapsed = start.elapsed();
-        println!("Update took {}ms", elapsed.as_micros() as f32 / 1000.0);
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.viewer.resize(size.width, size.height);
     }
-    fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.window_size = new_size;
-            self.window_config.width = new_size.width;
-            self.window_config.height = new_size.height;
-            self.surface.configure(&self.device, &self.window_config);
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        #[cfg(feature = "ui")]
+        if self.overlay.on_event(event) {
+            return true;
+        }
+        let viewer = &mut self.viewer;
+        let (ctrl, shift) = (self.modifiers.ctrl(), self.modifiers.shift());
+        let key = match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => *key,
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                return false;
+            }
+            WindowEvent::DroppedFile(path) => {
+                // Holding shift adds to the board rather than replacing it
+                if let Err(err) = viewer.load(path, shift) {
+                    log::warn!("Couldn't load {}: {err}", path.display());
+                }
+                return true;
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                match state {
+                    ElementState::Pressed => viewer.press(),
+                    ElementState::Released => viewer.release(),
+                }
+                return true;
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Middle | MouseButton::Right,
+                ..
+            } => {
+                viewer.set_panning(*state == ElementState::Pressed);
+                return true;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                viewer.move_cursor([position.x as f32, position.y as f32]);
+                return true;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                viewer.zoom(steps);
+                return true;
+            }
+            _ => return false,
+        };
+        match key {
+            #[cfg(feature = "ui")]
+            VirtualKeyCode::F1 => self.overlay.panel.visible = !self.overlay.panel.visible,
+            // Copy, or cut
+            VirtualKeyCode::C | VirtualKeyCode::X if ctrl => viewer.copy(key == VirtualKeyCode::X),
+            VirtualKeyCode::V if ctrl => viewer.paste(),
+            // Shift clears everything but the selection
+            VirtualKeyCode::Delete => viewer.clear(shift),
+            VirtualKeyCode::Key5 if ctrl => viewer.fill(),
+            VirtualKeyCode::Minus if ctrl => viewer.change_fill_density(-1.0),
+            VirtualKeyCode::Equals if ctrl => viewer.change_fill_density(1.0),
+            // Turn the pattern before placing it, or cancel
+            VirtualKeyCode::R if viewer.pasting() && !ctrl && shift => {
+                viewer.transform_clipboard(Clip::rotate_anticlockwise)
+            }
+            VirtualKeyCode::R if viewer.pasting() && !ctrl => {
+                viewer.transform_clipboard(Clip::rotate_clockwise)
+            }
+            VirtualKeyCode::X if viewer.pasting() && !ctrl => {
+                viewer.transform_clipboard(Clip::flip_horizontal)
+            }
+            VirtualKeyCode::Y if viewer.pasting() && !ctrl => {
+                viewer.transform_clipboard(Clip::flip_vertical)
+            }
+            VirtualKeyCode::Escape if viewer.pasting() && !ctrl => viewer.set_pasting(false),
+            VirtualKeyCode::Space => viewer.set_running(!viewer.running()),
+            // Ctrl+Shift+Z redoes too
+            VirtualKeyCode::Z if ctrl => viewer.undo(shift),
+            VirtualKeyCode::Y if ctrl => viewer.undo(true),
+            // Step back or forward through time while paused, a hundred
+            // generations at a time with Shift
+            VirtualKeyCode::Comma | VirtualKeyCode::Period => {
+                let steps = if shift { 100 } else { 1 };
+                let generation = viewer.life().generation();
+                viewer.seek(match key {
+                    VirtualKeyCode::Comma => generation.saturating_sub(steps),
+                    _ => generation + steps,
+                });
+            }
+            // Back to the very start
+            VirtualKeyCode::Home => viewer.seek(0),
+            VirtualKeyCode::S | VirtualKeyCode::O if ctrl => {
+                let result = match key {
+                    VirtualKeyCode::S => viewer.save_session(),
+                    _ => viewer.load_session(),
+                };
+                match result {
+                    Ok(()) => log::info!(
+                        "Generation {}, session {}",
+                        viewer.life().generation(),
+                        viewer.session().display()
+                    ),
+                    Err(err) => log::warn!("Session {}: {err}", viewer.session().display()),
+                }
+            }
+            VirtualKeyCode::H => viewer.set_hexagonal(!viewer.renderer().hexagonal()),
+            VirtualKeyCode::F => viewer.fit(),
+            VirtualKeyCode::G => viewer.toggle_grid(),
+            VirtualKeyCode::S => viewer.toggle_smoothing(),
+            VirtualKeyCode::M => viewer.next_mode(),
+            // Shorten or lengthen the colour ramp
+            VirtualKeyCode::LBracket => viewer.scale_ramp(false),
+            VirtualKeyCode::RBracket => viewer.scale_ramp(true),
+            VirtualKeyCode::T => viewer.next_palette(),
+            VirtualKeyCode::P => {
+                let populations = viewer.populations();
+                let live = &populations[1..];
+                log::info!(
+                    "Population {} by state {:?}",
+                    live.iter().sum::<usize>(),
+                    live
+                );
+            }
+            _ => return false,
         }
+        true
     }
-    fn input(&mut self, _: &WindowEvent) -> bool {
-        false
+    #[cfg(not(feature = "ui"))]
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        self.viewer.render()
     }
+    // The board with the panel over it
+    #[cfg(feature = "ui")]
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let start = Instant::now();
-        let res = self
-            .renderer
-            .render(&self.surface, &self.device, &self.queue);
-        let elapsed = start.elapsed();
-        println!("Render took {}ms", elapsed.as_micros() as f32 / 1000.0);
-        res
+        let viewer = &self.viewer;
+        let (counted, population) = self.population;
+        if self.overlay.panel.visible && counted.elapsed().as_millis() >= 500 {
+            let populations = viewer.populations();
+            self.population = (Instant::now(), Some(populations[1..].iter().sum()));
+        }
+        let status = ui::Status {
+            generation: viewer.life().generation(),
+            population,
+            rule: viewer.config().rule.to_string(),
+            width: viewer.config().width,
+            height: viewer.config().height,
+            running: viewer.running(),
+            speed: viewer.speed(),
+            hexagonal: viewer.renderer().hexagonal(),
+            palette: viewer.renderer().palette(),
+            update_time: viewer.update_time(),
+            render_time: viewer.render_time(),
+        };
+        let actions = self.overlay.update(&self.window, &status);
+        for action in actions {
+            self.act(action);
+        }
+        let overlay = &mut self.overlay;
+        self.viewer
+            .render_with(|device, queue, encoder, view, size| {
+                overlay.paint(device, queue, encoder, view, size)
+            })
+    }
+    #[cfg(feature = "ui")]
+    fn act(&mut self, action: ui::Action) {
+        let viewer = &mut self.viewer;
+        match action {
+            ui::Action::SetRunning(running) => viewer.set_running(running),
+            ui::Action::Step => viewer.seek(viewer.life().generation() + 1),
+            ui::Action::Undo => viewer.undo(false),
+            ui::Action::Redo => viewer.undo(true),
+            ui::Action::SetSpeed(speed) => viewer.set_speed(speed),
+            ui::Action::SetRule(rule) => match rule.parse::<rule::Rule>() {
+                Ok(rule) => viewer.set_rule(rule),
+                Err(err) => log::warn!("Rule {rule}: {err}"),
+            },
+            ui::Action::SetHexagonal(hexagonal) => viewer.set_hexagonal(hexagonal),
+            ui::Action::SetPalette(palette) => viewer.set_palette(&palette),
+            ui::Action::Load { path, add } => {
+                if let Err(err) = viewer.load(&path, add) {
+                    log::warn!("Couldn't load {}: {err}", path.display());
+                }
+                self.overlay.panel.reset_rule();
+            }
+            ui::Action::OpenSession(path) => {
+                viewer.set_session(path);
+                if let Err(err) = viewer.load_session() {
+                    log::warn!("Session {}: {err}", viewer.session().display());
+                }
+                self.overlay.panel.reset_rule();
+            }
+            ui::Action::SaveSession(path) => {
+                viewer.set_session(path);
+                if let Err(err) = viewer.save_session() {
+                    log::warn!("Session {}: {err}", viewer.session().display());
+                }
+            }
+        }
     }
 }
 
 async fn run() {
-    let config = life::Config {
-        width: WIDTH,
-        height: HEIGHT,
-    };
+    // libconway [--rule RULE] [--session FILE] runs RULE, a rulestring or a
+    // Golly .rule file, and carries on from FILE if there is one, saving back
+    // to it on closing
+    let mut session = None;
+    let mut config = life::Config::new(WIDTH, HEIGHT);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--session", Some(path)) => session = Some(PathBuf::from(path)),
+            ("--rule", Some(rule)) => {
+                let parsed = if rule.ends_with(".rule") {
+                    golly::read(&rule).map(rule::Rule::from)
+                } else {
+                    rule.parse()
+                        .map_err(|err: rule::RuleError| generate::Error::Invalid(err.to_string()))
+                };
+                match parsed {
+                    Ok(parsed) => config.rule = parsed,
+                    Err(err) => {
+                        eprintln!("Rule {rule}: {err}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => {
+                eprintln!("Usage: libconway [--rule RULE] [--session FILE]");
+                std::process::exit(2);
+            }
+        }
+    }
+
     let data = generate::Plaintext {
         source: "patterns/breeder_1.cells",
         x_offset: 10,
         y_offset: 740,
     }
-    .generate(&config);
+    .generate(&config)
+    .unwrap();
 
-    env_logger::init();
+    // Shows what the viewer's doing unless told otherwise
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("warn,libconway=info"),
+    )
+    .init();
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
     let mut state = State::new(window, data, config).await;
+    if let Some(path) = session {
+        // Rather than saving over a session that couldn't be read
+        if let Err(err) = state.viewer.open_session(path) {
+            eprintln!("Session {}: {err}", state.viewer.session().display());
+            std::process::exit(1);
+        }
+    }
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
-        } if window_id == state.window().id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
+        } if window_id == state.window().id() && !state.input(event) => match event {
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
                         ..
-                    } => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
-                    }
-                    _ => {}
+                    },
+                ..
+            } => {
+                if let Err(err) = state.viewer.close() {
+                    log::error!("Session {}: {err}", state.viewer.session().display());
                 }
+                *control_flow = ControlFlow::Exit
             }
-        }
+            WindowEvent::Resized(physical_size) => {
+                state.resize(*physical_size);
+            }
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                state.resize(**new_inner_size);
+            }
+            _ => {}
+        },
         Event::RedrawRequested(window_id) if window_id == state.window().id() => {
-            state.update();
+            state.viewer.update();
             match state.render() {
                 Ok(_) => {}
                 Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                    state.resize(state.window_size)
+                    state.resize(state.window().inner_size())
                 }
                 Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                 Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
@@ -0,0 +1,245 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use crate::generate::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub live: [u8; 3],
+    pub dead: [u8; 3],
+    // Outside the edges of the board
+    pub border: [u8; 3],
+    // Behind the board, where it doesn't fill the window
+    pub background: [u8; 3],
+    pub grid: [u8; 3],
+    // Tints the selection and the area being pasted over
+    pub selection: [u8; 3],
+    // A gradient for states from 2 up, such as the dying states of
+    // Generations rules. Without any, they take the live colour.
+    pub states: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    pub const MAX_STATES: usize = 16;
+
+    pub fn golly() -> Self {
+        Self {
+            live: [255, 255, 255],
+            dead: [48, 48, 48],
+            border: [112, 112, 112],
+            background: [48, 48, 48],
+            grid: [80, 80, 80],
+            selection: [75, 175, 0],
+            states: vec![[255, 0, 0], [255, 255, 0]],
+        }
+    }
+
+    pub fn lifeviewer() -> Self {
+        Self {
+            live: [255, 255, 255],
+            dead: [0, 0, 48],
+            border: [96, 96, 128],
+            background: [0, 0, 32],
+            grid: [32, 32, 96],
+            selection: [96, 160, 255],
+            states: vec![[255, 255, 0], [255, 0, 0], [64, 0, 0]],
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            live: [255, 255, 255],
+            dead: [0, 0, 0],
+            border: [255, 255, 0],
+            background: [0, 0, 0],
+            grid: [128, 128, 128],
+            selection: [0, 255, 255],
+            states: vec![[255, 255, 0], [255, 0, 255]],
+        }
+    }
+
+    // From the Okabe-Ito palette, which stays distinct under the common
+    // forms of colour blindness
+    pub fn colour_blind() -> Self {
+        Self {
+            live: [255, 255, 255],
+            dead: [0, 0, 0],
+            border: [86, 180, 233],
+            background: [24, 24, 24],
+            grid: [96, 96, 96],
+            selection: [0, 158, 115],
+            states: vec![[230, 159, 0], [213, 94, 0], [0, 114, 178]],
+        }
+    }
+
+    // The built in themes, by name
+    pub fn presets() -> Vec<(&'static str, Self)> {
+        vec![
+            ("default", Self::default()),
+            ("golly", Self::golly()),
+            ("lifeviewer", Self::lifeviewer()),
+            ("high-contrast", Self::high_contrast()),
+            ("colour-blind", Self::colour_blind()),
+        ]
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        Self::presets()
+            .into_iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, palette)| palette)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            live: [255, 255, 255],
+            dead: [0, 0, 0],
+            border: [0, 0, 0],
+            background: [26, 51, 77],
+            grid: [64, 64, 64],
+            selection: [75, 175, 0],
+            states: vec![[255, 204, 51], [77, 0, 0]],
+        }
+    }
+}
+
+// Either "r g b" or "#rrggbb"
+fn parse_colour(value: &str) -> Option<[u8; 3]> {
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|chr| chr.is_ascii_hexdigit()) {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let [_, r, g, b] = value.to_be_bytes();
+        return Some([r, g, b]);
+    }
+    let channels = value
+        .split_whitespace()
+        .map(|channel| channel.parse::<u8>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    channels.try_into().ok()
+}
+
+impl FromStr for Palette {
+    type Err = Error;
+
+    // One colour per line, as in "live 255 255 255" or "dead #303030", with
+    // a "state" line for each multi-state colour in order, or "states none"
+    // for no gradient, and "preset golly" to start from a built in theme.
+    // Anything left out keeps its default, and lines starting with '#' are
+    // comments.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut palette = Palette::default();
+        let mut states = None;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad_line = |message: String| Error::Parse {
+                line: line_number + 1,
+                message,
+            };
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            if key == "preset" {
+                palette = Palette::preset(value)
+                    .ok_or_else(|| bad_line(format!("unknown preset {value:?}")))?;
+                continue;
+            }
+            if line == "states none" {
+                states.get_or_insert_with(Vec::new);
+                continue;
+            }
+            let colour =
+                parse_colour(value).ok_or_else(|| bad_line(format!("bad colour {value:?}")))?;
+            match key {
+                "live" => palette.live = colour,
+                "dead" => palette.dead = colour,
+                "border" => palette.border = colour,
+                "background" => palette.background = colour,
+                "grid" => palette.grid = colour,
+                "selection" => palette.selection = colour,
+                "state" => {
+                    let states = states.get_or_insert_with(Vec::new);
+                    if states.len() == Palette::MAX_STATES {
+                        return Err(bad_line("too many state colours".into()));
+                    }
+                    states.push(colour);
+                }
+                _ => return Err(bad_line(format!("unknown key {key:?}"))),
+            }
+        }
+        if let Some(states) = states {
+            palette.states = states;
+        }
+        Ok(palette)
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colours = [
+            ("live", self.live),
+            ("dead", self.dead),
+            ("border", self.border),
+            ("background", self.background),
+            ("grid", self.grid),
+            ("selection", self.selection),
+        ];
+        let states = self.states.iter().map(|&colour| ("state", colour));
+        for (key, [r, g, b]) in colours.into_iter().chain(states) {
+            writeln!(f, "{key} #{r:02x}{g:02x}{b:02x}")?;
+        }
+        if self.states.is_empty() {
+            writeln!(f, "states none")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_preset() {
+        for (name, palette) in Palette::presets() {
+            assert_eq!(
+                palette.to_string().parse::<Palette>().unwrap(),
+                palette,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_no_state_colours() {
+        let palette = Palette {
+            states: vec![],
+            ..Palette::golly()
+        };
+        assert_eq!(palette.to_string().parse::<Palette>().unwrap(), palette);
+    }
+
+    #[test]
+    fn starts_from_a_preset() {
+        let palette = "preset golly\nlive 1 2 3\nstate #ff0000\n"
+            .parse::<Palette>()
+            .unwrap();
+        assert_eq!(palette.live, [1, 2, 3]);
+        assert_eq!(palette.dead, Palette::golly().dead);
+        assert_eq!(palette.states, vec![[255, 0, 0]]);
+    }
+
+    #[test]
+    fn rejects_bad_colours() {
+        assert!("live #12345".parse::<Palette>().is_err());
+        assert!("colour 1 2 3".parse::<Palette>().is_err());
+    }
+}
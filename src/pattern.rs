@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+
+use crate::generate::{self, Error, Generator};
+
+// A sparse set of cells, keyed by (x, y), relative to an arbitrary origin
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pattern {
+    cells: BTreeMap<(i32, i32), u32>,
+}
+
+impl Pattern {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn glider() -> Self {
+        [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]
+            .into_iter()
+            .collect()
+    }
+
+    pub fn block() -> Self {
+        [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().collect()
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, state: u32) {
+        if state == 0 {
+            self.cells.remove(&(x, y));
+        } else {
+            self.cells.insert((x, y), state);
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> u32 {
+        self.cells.get(&(x, y)).copied().unwrap_or(0)
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (i32, i32, u32)> + '_ {
+        self.cells.iter().map(|(&(x, y), &state)| (x, y, state))
+    }
+
+    pub fn population(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    // (min_x, min_y, max_x, max_y), inclusive
+    pub fn bounding_box(&self) -> Option<(i32, i32, i32, i32)> {
+        let mut cells = self.cells.keys();
+        let &(x, y) = cells.next()?;
+        Some(
+            cells.fold((x, y, x, y), |(min_x, min_y, max_x, max_y), &(x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            }),
+        )
+    }
+
+    fn map(&self, f: impl Fn(i32, i32) -> (i32, i32)) -> Self {
+        Self {
+            cells: self
+                .cells
+                .iter()
+                .map(|(&(x, y), &state)| (f(x, y), state))
+                .collect(),
+        }
+    }
+
+    // Coordinates wrap around at the limits of i32 rather than overflowing,
+    // here and in the rotations and flips, so every transform can be undone
+    pub fn translate(&self, dx: i32, dy: i32) -> Self {
+        self.map(|x, y| (x.wrapping_add(dx), y.wrapping_add(dy)))
+    }
+
+    // Moves the bounding box to start at (0, 0)
+    pub fn normalise(&self) -> Self {
+        match self.bounding_box() {
+            Some((min_x, min_y, _, _)) => {
+                self.translate(min_x.wrapping_neg(), min_y.wrapping_neg())
+            }
+            None => self.clone(),
+        }
+    }
+
+    // Rotations are clockwise about the origin, with y pointing down
+    pub fn rotate_90(&self) -> Self {
+        self.map(|x, y| (y.wrapping_neg(), x))
+    }
+
+    pub fn rotate_180(&self) -> Self {
+        self.map(|x, y| (x.wrapping_neg(), y.wrapping_neg()))
+    }
+
+    pub fn rotate_270(&self) -> Self {
+        self.map(|x, y| (y, x.wrapping_neg()))
+    }
+
+    pub fn flip_horizontal(&self) -> Self {
+        self.map(|x, y| (x.wrapping_neg(), y))
+    }
+
+    pub fn flip_vertical(&self) -> Self {
+        self.map(|x, y| (x, y.wrapping_neg()))
+    }
+
+    // Reflects across the x = y diagonal
+    pub fn flip_diagonal(&self) -> Self {
+        self.map(|x, y| (y, x))
+    }
+
+    // Cells present in both take their state from self
+    pub fn union(&self, other: &Self) -> Self {
+        let mut cells = other.cells.clone();
+        cells.extend(self.cells.iter().map(|(&k, &v)| (k, v)));
+        Self { cells }
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            cells: self
+                .cells
+                .iter()
+                .filter(|(k, _)| other.cells.contains_key(k))
+                .map(|(&k, &v)| (k, v))
+                .collect(),
+        }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        Self {
+            cells: self
+                .cells
+                .iter()
+                .filter(|(k, _)| !other.cells.contains_key(k))
+                .map(|(&k, &v)| (k, v))
+                .collect(),
+        }
+    }
+}
+
+impl FromIterator<(i32, i32)> for Pattern {
+    fn from_iter<T: IntoIterator<Item = (i32, i32)>>(iter: T) -> Self {
+        Self {
+            cells: iter.into_iter().map(|position| (position, 1)).collect(),
+        }
+    }
+}
+
+impl Generator for Pattern {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        Scene {
+            patterns: vec![(self, 0, 0)],
+        }
+        .generate(config)
+    }
+}
+
+// Several patterns, each translated by its offset, placed onto one board
+pub struct Scene {
+    pub patterns: Vec<(Pattern, i32, i32)>,
+}
+
+impl Generator for Scene {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let mut data = generate::empty_board(config);
+        for (pattern, x_offset, y_offset) in self.patterns {
+            for (x, y, state) in pattern.cells() {
+                generate::set_cell(
+                    &mut data,
+                    config,
+                    x as i64 + x_offset as i64,
+                    y as i64 + y_offset as i64,
+                    state,
+                )?;
+            }
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::Config;
+
+    #[test]
+    fn rotates_back_to_the_start() {
+        let glider = Pattern::glider();
+        let quarter = glider.rotate_90();
+        assert_ne!(quarter, glider);
+        assert_eq!(quarter.rotate_90(), glider.rotate_180());
+        assert_eq!(quarter.rotate_90().rotate_90(), glider.rotate_270());
+        assert_eq!(glider.rotate_270().rotate_90(), glider);
+        assert_eq!(
+            quarter.rotate_90().rotate_90().rotate_90().normalise(),
+            glider
+        );
+    }
+
+    #[test]
+    fn flips() {
+        let glider = Pattern::glider();
+        assert_eq!(glider.flip_horizontal().flip_horizontal(), glider);
+        assert_eq!(glider.flip_vertical().flip_vertical(), glider);
+        assert_eq!(glider.flip_diagonal().flip_diagonal(), glider);
+        assert_eq!(
+            glider.flip_horizontal().flip_vertical(),
+            glider.rotate_180()
+        );
+        assert_eq!(glider.flip_diagonal(), glider.rotate_90().flip_horizontal());
+        assert_eq!(
+            glider.flip_horizontal().normalise().get(0, 0),
+            glider.get(2, 0)
+        );
+    }
+
+    #[test]
+    fn translates_and_normalises() {
+        let glider = Pattern::glider();
+        let moved = glider.translate(5, -3);
+        assert_eq!(moved.bounding_box(), Some((5, -3, 7, -1)));
+        assert_eq!(moved.get(6, -3), 1);
+        assert_eq!(moved.normalise(), glider);
+        assert_eq!(Pattern::new().normalise(), Pattern::new());
+    }
+
+    #[test]
+    fn wraps_at_the_limits_of_i32() {
+        let edge = [(i32::MAX, 0)].into_iter().collect::<Pattern>();
+        assert_eq!(edge.translate(1, 0).get(i32::MIN, 0), 1);
+        let corner = [(i32::MIN, i32::MIN)].into_iter().collect::<Pattern>();
+        assert_eq!(corner.rotate_90().rotate_270(), corner);
+        assert_eq!(corner.flip_horizontal().flip_horizontal(), corner);
+        let far = [(i32::MIN, 0), (i32::MIN + 2, 1)]
+            .into_iter()
+            .collect::<Pattern>();
+        assert_eq!(far.normalise().bounding_box(), Some((0, 0, 2, 1)));
+    }
+
+    #[test]
+    fn combines_overlapping_patterns() {
+        let mut left = Pattern::block();
+        left.set(0, 0, 2);
+        let right = Pattern::block().translate(1, 0);
+        let union = left.union(&right);
+        assert_eq!(union.population(), 6);
+        // The overlap keeps the left pattern's states
+        assert_eq!(union.get(0, 0), 2);
+        assert_eq!(right.union(&left).get(1, 0), 1);
+        assert_eq!(
+            left.intersection(&right),
+            [(1, 0), (1, 1)].into_iter().collect()
+        );
+        let difference = left.difference(&right);
+        assert_eq!(difference.population(), 2);
+        assert_eq!((difference.get(0, 0), difference.get(0, 1)), (2, 1));
+        assert!(left.difference(&left).is_empty());
+    }
+
+    #[test]
+    fn places_several_patterns() {
+        let config = Config::new(12, 8);
+        let data = Scene {
+            patterns: vec![
+                (Pattern::glider(), 0, 0),
+                (Pattern::glider().flip_horizontal(), 9, 4),
+                (Pattern::block(), 5, 3),
+            ],
+        }
+        .generate(&config)
+        .unwrap();
+        let cell = |x: u32, y: u32| data[(y * config.width + x) as usize];
+        assert_eq!(data.iter().sum::<u32>(), 14);
+        assert_eq!((cell(1, 0), cell(0, 0)), (1, 0));
+        assert_eq!((cell(8, 4), cell(9, 6), cell(7, 6)), (1, 1, 1));
+        assert_eq!((cell(5, 3), cell(6, 4)), (1, 1));
+    }
+
+    #[test]
+    fn rejects_cells_off_the_board() {
+        let config = Config::new(4, 4);
+        let scene = Scene {
+            patterns: vec![(Pattern::block(), 0, 0), (Pattern::glider(), 2, 1)],
+        };
+        assert!(matches!(
+            scene.generate(&config),
+            Err(Error::OutOfBounds { x: 4, y: 2 })
+        ));
+        let far = Scene {
+            patterns: vec![(Pattern::block(), i32::MAX, 0)],
+        };
+        assert!(matches!(
+            far.generate(&config),
+            Err(Error::OutOfBounds { x, y: 0 }) if x == i32::MAX as i64
+        ));
+    }
+}
@@ -1,14 +1,23 @@
+use std::num::NonZeroU32;
+
+use crate::{
+    life::Pass,
+    palette::Palette,
+    selection::{Clip, Selection},
+};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendState, Buffer,
-    BufferUsages, Color, ColorTargetState, ColorWrites, CommandEncoderDescriptor, Device, Face,
-    FilterMode, FragmentState, FrontFace, IndexFormat, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue,
-    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
-    SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages,
-    Surface, SurfaceConfiguration, SurfaceError, TextureSampleType, TextureView,
-    TextureViewDescriptor, TextureViewDimension, VertexState,
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendState, Buffer, BufferBindingType, BufferUsages, Color, ColorTargetState, ColorWrites,
+    CommandEncoder, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor,
+    Device, Face, FilterMode, FragmentState, FrontFace, IndexFormat, LoadOp, MultisampleState,
+    Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue,
+    RenderPass, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, StorageTextureAccess, Surface, SurfaceConfiguration, SurfaceError,
+    Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
 };
 
 #[repr(C)]
@@ -61,20 +70,282 @@ impl Vertex {
     }
 }
 
+// What cells are coloured by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    States,
+    // Live cells by how many generations they've been alive
+    Age,
+    // Cells by how recently they were born or died, so that still lifes cool
+    // down while oscillators and reactions stay hot
+    Activity,
+}
+
+impl Mode {
+    pub fn next(self) -> Self {
+        match self {
+            Mode::States => Mode::Age,
+            Mode::Age => Mode::Activity,
+            Mode::Activity => Mode::States,
+        }
+    }
+}
+
+// A gradient through evenly spaced colours, reaching the last colour after
+// the given number of generations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ramp {
+    pub colours: Vec<[u8; 3]>,
+    pub generations: u32,
+}
+
+impl Ramp {
+    pub const MAX_COLOURS: usize = 8;
+}
+
+impl Default for Ramp {
+    fn default() -> Self {
+        Self {
+            colours: vec![
+                [64, 0, 96],
+                [192, 32, 48],
+                [255, 128, 0],
+                [255, 224, 64],
+                [255, 255, 255],
+            ],
+            generations: 100,
+        }
+    }
+}
+
+// Which part of the board is in view, as the board position at the centre of
+// the window and the number of pixels per cell. In hexagonal mode, positions
+// are in the hexagonal layout rather than cells.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+}
+
+impl Camera {
+    pub const MIN_ZOOM: f32 = 1.0 / 64.0;
+    pub const MAX_ZOOM: f32 = 256.0;
+
+    // As large as possible while still showing all of the area from min to max
+    pub fn fit(min: [f32; 2], max: [f32; 2], viewport: [f32; 2]) -> Self {
+        let zoom = (viewport[0] / (max[0] - min[0])).min(viewport[1] / (max[1] - min[1]));
+        Self {
+            x: (min[0] + max[0]) / 2.0,
+            y: (min[1] + max[1]) / 2.0,
+            zoom: zoom.clamp(Self::MIN_ZOOM, Self::MAX_ZOOM),
+        }
+    }
+
+    // The board position shown at a pixel
+    pub fn point(&self, pixel: [f32; 2], viewport: [f32; 2]) -> [f32; 2] {
+        [
+            self.x + (pixel[0] - viewport[0] / 2.0) / self.zoom,
+            self.y + (pixel[1] - viewport[1] / 2.0) / self.zoom,
+        ]
+    }
+
+    // Moves the view by a distance in pixels
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.x -= dx / self.zoom;
+        self.y -= dy / self.zoom;
+    }
+
+    // Keeps the point under the given pixel where it is
+    pub fn zoom_at(&mut self, factor: f32, pixel: [f32; 2], viewport: [f32; 2]) {
+        let offset = [pixel[0] - viewport[0] / 2.0, pixel[1] - viewport[1] / 2.0];
+        let zoom = (self.zoom * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.x += offset[0] / self.zoom - offset[0] / zoom;
+        self.y += offset[1] / self.zoom - offset[1] / zoom;
+        self.zoom = zoom;
+    }
+}
+
+// Lines between cells, with a bold line every so many cells as in Golly, or
+// none when bold is 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grid {
+    pub visible: bool,
+    pub bold: u32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            bold: 10,
+        }
+    }
+}
+
+fn to_rgba([r, g, b]: [u8; 3]) -> [f32; 4] {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]
+}
+
+// A palette as laid out in the fragment shader's uniform buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PaletteParams {
+    live: [f32; 4],
+    dead: [f32; 4],
+    border: [f32; 4],
+    grid: [f32; 4],
+    selection: [f32; 4],
+    states: u32,
+    _padding: [u32; 3],
+    state_colours: [[f32; 4]; Palette::MAX_STATES],
+}
+
+impl From<&Palette> for PaletteParams {
+    fn from(palette: &Palette) -> Self {
+        let mut state_colours = [[0.0; 4]; Palette::MAX_STATES];
+        let states = palette.states.iter().take(Palette::MAX_STATES);
+        for (colour, &state) in state_colours.iter_mut().zip(states.clone()) {
+            *colour = to_rgba(state);
+        }
+        Self {
+            live: to_rgba(palette.live),
+            dead: to_rgba(palette.dead),
+            border: to_rgba(palette.border),
+            grid: to_rgba(palette.grid),
+            selection: to_rgba(palette.selection),
+            states: states.len() as u32,
+            _padding: [0; 3],
+            state_colours,
+        }
+    }
+}
+
+// Render options as laid out in the fragment shader's uniform buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Settings {
+    hexagonal: u32,
+    mode: u32,
+    ramp_colours: u32,
+    ramp_generations: u32,
+    // The camera as (x, y, zoom, unused)
+    camera: [f32; 4],
+    // The size of the window in pixels
+    viewport: [f32; 2],
+    grid: u32,
+    grid_bold: u32,
+    smoothing: u32,
+    _padding: u32,
+    // Where the viewport starts in the target, in pixels
+    origin: [f32; 2],
+    // As (x, y, width, height), with a width of 0 for none
+    selection: [i32; 4],
+    paste: [i32; 4],
+    // Colours for individual states, used where alpha is set
+    colours: [[f32; 4]; 256],
+    ramp: [[f32; 4]; Ramp::MAX_COLOURS],
+}
+
+impl Settings {
+    // Extra colours past the maximum are dropped
+    fn set_ramp(&mut self, ramp: &Ramp) {
+        let colours = ramp.colours.iter().take(Ramp::MAX_COLOURS);
+        for (stop, &colour) in self.ramp.iter_mut().zip(colours.clone()) {
+            *stop = to_rgba(colour);
+        }
+        self.ramp_colours = colours.len() as u32;
+        self.ramp_generations = ramp.generations.max(1);
+    }
+}
+
+fn paste_bind_group(device: &Device, layout: &BindGroupLayout, states: &[u32]) -> BindGroup {
+    let buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Paste buffer"),
+        contents: bytemuck::cast_slice(states),
+        usage: BufferUsages::STORAGE,
+    });
+    device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+        label: Some("Paste bind group"),
+    })
+}
+
 pub struct Renderer {
+    mode: Mode,
+    camera: Camera,
+    grid: Grid,
+    ramp: Ramp,
+    palette: Palette,
+    palette_buffer: Buffer,
+    settings: Settings,
+    settings_buffer: Buffer,
     pipeline: RenderPipeline,
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     num_indices: u32,
     texture_bind_group: BindGroup,
+    paste_bind_group_layout: BindGroupLayout,
+    paste_bind_group: BindGroup,
+    // Colours the cells and averages them down a mip chain, for drawing
+    // zoomed out
+    colour_passes: Vec<Pass>,
 }
 
 impl Renderer {
-    pub fn new(
+    pub fn new(device: &Device, window_config: &SurfaceConfiguration, texture: &Texture) -> Self {
+        Self::with_format(
+            device,
+            window_config.format,
+            [window_config.width, window_config.height],
+            texture,
+        )
+    }
+    // For drawing into targets of any format, with a viewport of the given
+    // size in pixels
+    pub fn with_format(
         device: &Device,
-        window_config: &SurfaceConfiguration,
-        texture_view: &TextureView,
+        format: TextureFormat,
+        viewport: [u32; 2],
+        texture: &Texture,
     ) -> Self {
+        let texture_view = &texture.create_view(&TextureViewDescriptor::default());
+        let size = texture.size();
+        // Down to a single texel
+        let mip_level_count = u32::BITS - size.width.max(size.height).leading_zeros();
+        let colour_texture = device.create_texture(&TextureDescriptor {
+            label: Some("Colour texture"),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let colour_levels = (0..mip_level_count)
+            .map(|level| {
+                colour_texture.create_view(&TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        let colour_view = colour_texture.create_view(&TextureViewDescriptor::default());
+        let colour_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
         let texture_sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
@@ -103,20 +374,111 @@ impl Renderer {
                         ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
                         count: None,
                     },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None,
+                    },
                 ],
                 label: Some("Texture bind group layout"),
             });
+        let mut settings = Settings {
+            hexagonal: 0,
+            mode: 0,
+            ramp_colours: 0,
+            ramp_generations: 0,
+            camera: [0.0; 4],
+            viewport: viewport.map(|length| length as f32),
+            grid: 0,
+            grid_bold: 0,
+            smoothing: 1,
+            _padding: 0,
+            origin: [0.0; 2],
+            selection: [0; 4],
+            paste: [0; 4],
+            colours: [[0.0; 4]; 256],
+            ramp: [[0.0; 4]; Ramp::MAX_COLOURS],
+        };
+        let ramp = Ramp::default();
+        settings.set_ramp(&ramp);
+        let camera = Camera {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1.0,
+        };
+        settings.camera = [camera.x, camera.y, camera.zoom, 0.0];
+        let grid = Grid::default();
+        settings.grid = grid.visible as u32;
+        settings.grid_bold = grid.bold;
+        let settings_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Settings buffer"),
+            contents: bytemuck::bytes_of(&settings),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let palette = Palette::default();
+        let palette_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Palette buffer"),
+            contents: bytemuck::bytes_of(&PaletteParams::from(&palette)),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
         let texture_bind_group = device.create_bind_group(&BindGroupDescriptor {
             layout: &texture_bind_group_layout,
             entries: &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: BindingResource::TextureView(&texture_view),
+                    resource: BindingResource::TextureView(texture_view),
                 },
                 BindGroupEntry {
                     binding: 1,
                     resource: BindingResource::Sampler(&texture_sampler),
                 },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: settings_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: palette_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&colour_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::Sampler(&colour_sampler),
+                },
             ],
             label: Some("Texture bind group"),
         });
@@ -124,9 +486,132 @@ impl Renderer {
             label: Some("Main shader"),
             source: ShaderSource::Wgsl(include_str!("../shaders/shader.wgsl").into()),
         });
+
+        // The cell texture isn't filterable, which an automatic layout would
+        // expect, so colouring needs its layout spelled out
+        let uniform = BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let colour_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: uniform,
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: uniform,
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: TextureFormat::Rgba8Unorm,
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("Colour bind group layout"),
+            });
+        let colour_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Colour pipeline layout"),
+            bind_group_layouts: &[&colour_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let workgroups = |level: u32| {
+            let width = (size.width >> level).max(1);
+            let height = (size.height >> level).max(1);
+            (width.div_ceil(8), height.div_ceil(8))
+        };
+        let mut colour_passes = vec![Pass {
+            pipeline: device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("Colour pipeline"),
+                layout: Some(&colour_pipeline_layout),
+                module: &shader,
+                entry_point: "colour_cells",
+            }),
+            bind_group: device.create_bind_group(&BindGroupDescriptor {
+                layout: &colour_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: settings_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: palette_buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: BindingResource::TextureView(&colour_levels[0]),
+                    },
+                ],
+                label: Some("Colour bind group"),
+            }),
+            workgroups: workgroups(0),
+        }];
+        let downsample = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Downsample shader"),
+            source: ShaderSource::Wgsl(include_str!("../shaders/downsample.wgsl").into()),
+        });
+        for level in 1..mip_level_count {
+            colour_passes.push(Pass::new(
+                device,
+                &downsample,
+                "main",
+                &[
+                    (
+                        0,
+                        BindingResource::TextureView(&colour_levels[level as usize - 1]),
+                    ),
+                    (
+                        1,
+                        BindingResource::TextureView(&colour_levels[level as usize]),
+                    ),
+                ],
+                workgroups(level),
+            ));
+        }
+        let paste_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("Paste bind group layout"),
+        });
+        let paste_bind_group = paste_bind_group(device, &paste_bind_group_layout, &[0]);
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Render pipeline layout"),
-            bind_group_layouts: &[&texture_bind_group_layout],
+            bind_group_layouts: &[&texture_bind_group_layout, &paste_bind_group_layout],
             push_constant_ranges: &[],
         });
         let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -141,7 +626,7 @@ impl Renderer {
                 module: &shader,
                 entry_point: "fs_main",
                 targets: &[Some(ColorTargetState {
-                    format: window_config.format,
+                    format,
                     blend: Some(BlendState {
                         color: BlendComponent::REPLACE,
                         alpha: BlendComponent::REPLACE,
@@ -178,13 +663,230 @@ impl Renderer {
         });
         let num_indices = INDICES.len() as u32;
         Self {
+            mode: Mode::States,
+            camera,
+            grid,
+            ramp,
+            palette,
+            palette_buffer,
+            settings,
+            settings_buffer,
             pipeline,
             vertex_buffer,
             index_buffer,
             num_indices,
             texture_bind_group,
+            paste_bind_group_layout,
+            paste_bind_group,
+            colour_passes,
+        }
+    }
+    pub fn hexagonal(&self) -> bool {
+        self.settings.hexagonal != 0
+    }
+    // Draws cells as hexagons, for rules on the hexagonal neighbourhood
+    pub fn set_hexagonal(&mut self, queue: &Queue, hexagonal: bool) {
+        self.settings.hexagonal = hexagonal as u32;
+        self.write_settings(queue);
+    }
+    pub fn camera(&self) -> Camera {
+        self.camera
+    }
+    pub fn set_camera(&mut self, queue: &Queue, camera: Camera) {
+        self.camera = camera;
+        self.settings.camera = [camera.x, camera.y, camera.zoom, 0.0];
+        self.write_settings(queue);
+    }
+    pub fn viewport(&self) -> [f32; 2] {
+        self.settings.viewport
+    }
+    // Call when the window changes size, so the camera keeps its zoom
+    pub fn resize(&mut self, queue: &Queue, width: u32, height: u32) {
+        self.settings.viewport = [width as f32, height as f32];
+        self.write_settings(queue);
+    }
+    pub fn origin(&self) -> [f32; 2] {
+        self.settings.origin
+    }
+    // Draws into just part of the target, the rectangle of the given size
+    // with its top left at origin, in pixels
+    pub fn set_viewport(&mut self, queue: &Queue, origin: [f32; 2], size: [f32; 2]) {
+        self.settings.origin = origin;
+        self.settings.viewport = size;
+        self.write_settings(queue);
+    }
+    // Shows the whole of a board with the given size
+    pub fn fit(&mut self, queue: &Queue, width: u32, height: u32) {
+        let (width, height) = (width as f32, height as f32);
+        let (min, max) = if self.hexagonal() {
+            // The parallelogram of hexagons, as laid out in the shader
+            let sqrt_3 = 3f32.sqrt();
+            (
+                [-height / 2.0, -1.0 / sqrt_3],
+                [width - 0.5, (height - 1.0) * sqrt_3 / 2.0 + 1.0 / sqrt_3],
+            )
+        } else {
+            ([0.0, 0.0], [width, height])
+        };
+        let camera = Camera::fit(min, max, self.viewport());
+        self.set_camera(queue, camera);
+    }
+    pub fn grid(&self) -> Grid {
+        self.grid
+    }
+    pub fn set_grid(&mut self, queue: &Queue, grid: Grid) {
+        self.grid = grid;
+        self.settings.grid = grid.visible as u32;
+        self.settings.grid_bold = grid.bold;
+        self.write_settings(queue);
+    }
+    pub fn smoothing(&self) -> bool {
+        self.settings.smoothing != 0
+    }
+    // Averages cells together when zoomed out past one cell per pixel
+    pub fn set_smoothing(&mut self, queue: &Queue, smoothing: bool) {
+        self.settings.smoothing = smoothing as u32;
+        self.write_settings(queue);
+    }
+    // The cell under a pixel of the target, which may be off the board
+    pub fn cell_at(&self, pixel: [f32; 2]) -> (i32, i32) {
+        let [origin_x, origin_y] = self.origin();
+        let pixel = [pixel[0] - origin_x, pixel[1] - origin_y];
+        let [x, y] = self.camera.point(pixel, self.viewport());
+        if !self.hexagonal() {
+            return (x.floor() as i32, y.floor() as i32);
+        }
+        // Rounds to the nearest hexagon as the shader does
+        let sqrt_3 = 3f32.sqrt();
+        let q = x - y / sqrt_3;
+        let r = 2.0 * y / sqrt_3;
+        let cube = [q, -q - r, r];
+        let mut rounded = cube.map(f32::round);
+        let error = [0, 1, 2].map(|i| (rounded[i] - cube[i]).abs());
+        if error[0] > error[1] && error[0] > error[2] {
+            rounded[0] = -rounded[1] - rounded[2];
+        } else if error[2] > error[1] {
+            rounded[2] = -rounded[0] - rounded[1];
+        }
+        let row = rounded[2] as i32;
+        (rounded[0] as i32 + row, row)
+    }
+    pub fn set_selection(&mut self, queue: &Queue, selection: Option<Selection>) {
+        self.settings.selection = match selection {
+            Some(Selection {
+                x,
+                y,
+                width,
+                height,
+            }) => [x as i32, y as i32, width as i32, height as i32],
+            None => [0; 4],
+        };
+        self.write_settings(queue);
+    }
+    // Shows a pattern waiting to be pasted, at the position from move_paste
+    pub fn set_paste(&mut self, device: &Device, queue: &Queue, clip: Option<&Clip>) {
+        let (width, height, states) = match clip {
+            Some(clip) if clip.width > 0 && clip.height > 0 => {
+                (clip.width as i32, clip.height as i32, clip.states())
+            }
+            _ => (0, 0, vec![0]),
+        };
+        self.paste_bind_group = paste_bind_group(device, &self.paste_bind_group_layout, &states);
+        self.settings.paste[2..].copy_from_slice(&[width, height]);
+        self.write_settings(queue);
+    }
+    // Puts the top left of the pasted pattern at a cell
+    pub fn move_paste(&mut self, queue: &Queue, x: i32, y: i32) {
+        self.settings.paste[..2].copy_from_slice(&[x, y]);
+        self.write_settings(queue);
+    }
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, queue: &Queue, mode: Mode) {
+        self.mode = mode;
+        self.settings.mode = match mode {
+            Mode::States => 0,
+            Mode::Age => 1,
+            Mode::Activity => 2,
+        };
+        self.write_settings(queue);
+    }
+    pub fn ramp(&self) -> &Ramp {
+        &self.ramp
+    }
+    // The gradient for the age and activity modes
+    pub fn set_ramp(&mut self, queue: &Queue, ramp: &Ramp) {
+        self.ramp = ramp.clone();
+        self.settings.set_ramp(ramp);
+        self.write_settings(queue);
+    }
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+    pub fn set_palette(&mut self, queue: &Queue, palette: &Palette) {
+        self.palette = palette.clone();
+        let params = PaletteParams::from(palette);
+        queue.write_buffer(&self.palette_buffer, 0, bytemuck::bytes_of(&params));
+    }
+    fn write_settings(&self, queue: &Queue) {
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(&self.settings));
+    }
+    // Overrides the default colours of the given states, as from a Golly
+    // rule's @COLORS section
+    pub fn set_colours(&mut self, queue: &Queue, colours: &[(u32, [u8; 3])]) {
+        self.settings.colours = [[0.0; 4]; 256];
+        for &(state, [r, g, b]) in colours {
+            if let Some(colour) = self.settings.colours.get_mut(state as usize) {
+                *colour = to_rgba([r, g, b]);
+            }
+        }
+        self.write_settings(queue);
+    }
+    // Has to be recorded before the render pass, so before draw when drawing
+    // into a pass of your own
+    pub fn prepare(&self, encoder: &mut CommandEncoder) {
+        if self.smoothing() && !self.hexagonal() && self.camera.zoom < 1.0 {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Colour pass"),
+            });
+            for pass in &self.colour_passes {
+                pass.dispatch(&mut compute_pass);
+            }
         }
     }
+    // Draws the board over the viewport of a render pass begun elsewhere,
+    // whose target has the format the renderer was made for
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        let [x, y] = self.settings.origin;
+        let [width, height] = self.settings.viewport;
+        render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.paste_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+    // Records drawing the board over the viewport of a texture, leaving the
+    // rest of it as it was, for the caller to submit
+    pub fn encode(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        self.encode_with(encoder, view, LoadOp::Load);
+    }
+    fn encode_with(&self, encoder: &mut CommandEncoder, view: &TextureView, load: LoadOp<Color>) {
+        self.prepare(encoder);
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Render pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        self.draw(&mut render_pass);
+    }
+    // Draws a frame to the whole of a window's surface and presents it
     pub fn render(
         &mut self,
         surface: &Surface,
@@ -198,31 +900,14 @@ impl Renderer {
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
             label: Some("Render encoder"),
         });
-
-        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-            label: Some("Render pass"),
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: Operations {
-                    load: LoadOp::Clear(Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
-                    store: true,
-                },
-            })],
-            depth_stencil_attachment: None,
-        });
-        render_pass.set_pipeline(&self.pipeline);
-        render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
-        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
-        drop(render_pass);
-
+        let [r, g, b, a] = to_rgba(self.palette.background);
+        let background = Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: a as f64,
+        };
+        self.encode_with(&mut encoder, &view, LoadOp::Clear(background));
         queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
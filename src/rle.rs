@@ -0,0 +1,276 @@
+use std::{fmt::Write, fs};
+
+use crate::{
+    generate::{Error, Generator},
+    pattern::{Pattern, Scene},
+};
+
+// Golly wraps lines at 70 characters
+const LINE_LENGTH: usize = 70;
+
+pub struct RleFile {
+    pub name: Option<String>,
+    pub comments: Vec<String>,
+    pub rule: Option<String>,
+    pub pattern: Pattern,
+}
+
+// Multi-state files write states 1 to 24 as 'A' to 'X', and higher states
+// with a prefix from 'p' to 'y', so 25 is "pA"
+fn state_name(state: u32) -> String {
+    let letter = (b'A' + ((state - 1) % 24) as u8) as char;
+    match (state - 1) / 24 {
+        0 => letter.to_string(),
+        prefix => format!("{}{letter}", (b'o' + prefix as u8) as char),
+    }
+}
+
+pub fn parse(text: &str) -> Result<RleFile, Error> {
+    let mut name = None;
+    let mut comments = vec![];
+    let mut rule = None;
+    let mut pattern = Pattern::new();
+    let (mut x, mut y) = (0, 0);
+    let mut count = None::<i32>;
+    let mut prefix = None::<u32>;
+    let mut first_line = true;
+
+    'lines: for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let bad_line = |message: String| Error::Parse {
+            line: line_number + 1,
+            message,
+        };
+        if let Some(comment) = line.strip_prefix('#') {
+            let mut chars = comment.chars();
+            let kind = chars.next();
+            let value = chars.as_str().trim().to_string();
+            match kind {
+                Some('N') => name = Some(value),
+                Some('C' | 'c') => comments.push(value),
+                Some('r') => rule = Some(value),
+                _ => {}
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        // Only the first line can be the header, as data lines can start
+        // with x-prefixed states like xA
+        let header = std::mem::replace(&mut first_line, false)
+            && line
+                .strip_prefix('x')
+                .is_some_and(|rest| rest.trim_start().starts_with('='));
+        if header {
+            // x = 3, y = 3, rule = B3/S23, where the rule runs to the end of
+            // the line since rules like R5,C0,M1,S34..58,B34..45,NM have
+            // commas of their own
+            let (sizes, rest) = match line.find("rule") {
+                Some(start) => (&line[..start], Some(&line[start + "rule".len()..])),
+                None => (line, None),
+            };
+            for field in sizes.split(',').filter(|field| !field.trim().is_empty()) {
+                match field.split_once('=') {
+                    Some((key, _)) if ["x", "y"].contains(&key.trim()) => {}
+                    _ => return Err(bad_line(format!("bad header field {field:?}"))),
+                }
+            }
+            if let Some(rest) = rest {
+                let value = rest
+                    .trim_start()
+                    .strip_prefix('=')
+                    .ok_or_else(|| bad_line(format!("bad header field {rest:?}")))?;
+                rule = Some(value.trim().to_string());
+            }
+            continue;
+        }
+
+        for chr in line.chars() {
+            if let Some(digit) = chr.to_digit(10) {
+                count = Some(count.unwrap_or(0) * 10 + digit as i32);
+                continue;
+            }
+            let run = count.take().unwrap_or(1);
+            let state = match (prefix.take(), chr) {
+                (None, 'b' | '.') => 0,
+                (None, 'o') => 1,
+                (None, '$') => {
+                    x = 0;
+                    y += run;
+                    continue;
+                }
+                (None, '!') => break 'lines,
+                (None, 'p'..='y') => {
+                    prefix = Some(chr as u32 - 'o' as u32);
+                    count = Some(run);
+                    continue;
+                }
+                (prefix, 'A'..='X') => prefix.unwrap_or(0) * 24 + (chr as u32 - 'A' as u32) + 1,
+                (_, chr) if chr.is_whitespace() => continue,
+                (_, chr) => return Err(bad_line(format!("unexpected character {chr:?}"))),
+            };
+            for _ in 0..run {
+                pattern.set(x, y, state);
+                x += 1;
+            }
+        }
+    }
+    Ok(RleFile {
+        name,
+        comments,
+        rule,
+        pattern,
+    })
+}
+
+pub struct Rle {
+    pub source: &'static str,
+    pub x_offset: u32,
+    pub y_offset: u32,
+}
+
+impl Rle {
+    pub fn read(&self) -> Result<RleFile, Error> {
+        parse(&fs::read_to_string(self.source)?)
+    }
+}
+
+impl Generator for Rle {
+    fn generate(self, config: &crate::life::Config) -> Result<Vec<u32>, Error> {
+        let file = self.read()?;
+        Scene {
+            patterns: vec![(file.pattern, self.x_offset as i32, self.y_offset as i32)],
+        }
+        .generate(config)
+    }
+}
+
+// Lays out runs in lines of at most LINE_LENGTH characters
+struct Writer {
+    out: String,
+    line: usize,
+    // The pending run, as (count, token)
+    run: Option<(i32, String)>,
+}
+
+impl Writer {
+    fn push(&mut self, count: i32, token: String) {
+        match &mut self.run {
+            Some((pending, pending_token)) if *pending_token == token => *pending += count,
+            _ => {
+                self.flush();
+                self.run = Some((count, token));
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let Some((count, token)) = self.run.take() else {
+            return;
+        };
+        let item = match count {
+            1 => token,
+            count => format!("{count}{token}"),
+        };
+        if self.line + item.len() > LINE_LENGTH {
+            self.out.push('\n');
+            self.line = 0;
+        }
+        self.line += item.len();
+        self.out.push_str(&item);
+    }
+}
+
+pub fn write(pattern: &Pattern, rule: Option<&str>) -> String {
+    let pattern = pattern.normalise();
+    let (width, height) = match pattern.bounding_box() {
+        Some((_, _, max_x, max_y)) => (max_x + 1, max_y + 1),
+        None => (0, 0),
+    };
+    let multi_state = pattern.cells().any(|(_, _, state)| state > 1);
+
+    let mut out = format!("x = {width}, y = {height}");
+    if let Some(rule) = rule {
+        write!(out, ", rule = {rule}").unwrap();
+    }
+    out.push('\n');
+
+    let mut writer = Writer {
+        out,
+        line: 0,
+        run: None,
+    };
+    let mut cells = pattern.cells().collect::<Vec<_>>();
+    cells.sort_unstable_by_key(|&(x, y, _)| (y, x));
+    let (mut x, mut y) = (0, 0);
+    for (cell_x, cell_y, state) in cells {
+        if cell_y > y {
+            writer.push(cell_y - y, "$".into());
+            (x, y) = (0, cell_y);
+        }
+        if cell_x > x {
+            let dead = if multi_state { "." } else { "b" };
+            writer.push(cell_x - x, dead.into());
+        }
+        let token = if multi_state {
+            state_name(state)
+        } else {
+            "o".into()
+        };
+        writer.push(1, token);
+        x = cell_x + 1;
+    }
+    writer.push(1, "!".into());
+    writer.flush();
+    writer.out.push('\n');
+    writer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_glider() {
+        let file = parse("#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n").unwrap();
+        assert_eq!(file.name.as_deref(), Some("Glider"));
+        assert_eq!(file.rule.as_deref(), Some("B3/S23"));
+        assert_eq!(file.pattern, Pattern::glider());
+    }
+
+    #[test]
+    fn round_trips_a_larger_than_life_rule() {
+        let rule = "R5,C0,M1,S34..58,B34..45,NM";
+        let text = write(&Pattern::glider(), Some(rule));
+        assert!(text.starts_with("x = 3, y = 3, rule = R5,C0,M1,S34..58,B34..45,NM\n"));
+        let file = parse(&text).unwrap();
+        assert_eq!(file.rule.as_deref(), Some(rule));
+        assert_eq!(file.pattern, Pattern::glider());
+    }
+
+    #[test]
+    fn round_trips_multiple_states() {
+        let mut pattern = Pattern::new();
+        pattern.set(0, 0, 1);
+        pattern.set(2, 0, 24);
+        pattern.set(1, 3, 25);
+        pattern.set(40, 3, 255);
+        let text = write(&pattern, Some("B3/S23/256"));
+        assert_eq!(parse(&text).unwrap().pattern, pattern);
+    }
+
+    #[test]
+    fn rejects_unknown_header_fields() {
+        assert!(parse("x = 3, z = 3\nbo!\n").is_err());
+    }
+
+    #[test]
+    fn reads_data_lines_starting_with_x_prefixed_states() {
+        let file = parse("x = 3, y = 2, rule = B3/S23/256\nxA2o$\nxB!\n").unwrap();
+        assert_eq!(file.pattern.get(0, 0), 217);
+        assert_eq!(file.pattern.get(0, 1), 218);
+        // Without a header the first line is data too
+        assert_eq!(parse("xAo!\n").unwrap().pattern.population(), 2);
+    }
+}
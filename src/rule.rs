@@ -0,0 +1,712 @@
+use std::{fmt, str::FromStr};
+
+use crate::golly::GollyRule;
+
+#[derive(Debug)]
+pub struct RuleError(pub String);
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid rule: {}", self.0)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+// Neighbourhoods are 9 bit masks of the 3x3 block in reading order, so bit 0
+// is the north west neighbour and bit 4 is the cell itself
+const CENTRE: usize = 1 << 4;
+const NEIGHBOURS: usize = 0x1ef;
+
+// Hensel notation letters for 0-4 neighbours, each with a representative
+// neighbourhood. Counts 5-8 use the same letters for the complements.
+const HENSEL: [&[(char, usize)]; 5] = [
+    &[],
+    &[('c', 1), ('e', 2)],
+    &[
+        ('c', 5),
+        ('e', 10),
+        ('a', 3),
+        ('i', 40),
+        ('k', 33),
+        ('n', 68),
+    ],
+    &[
+        ('c', 69),
+        ('e', 42),
+        ('a', 11),
+        ('i', 7),
+        ('k', 98),
+        ('n', 13),
+        ('j', 14),
+        ('q', 70),
+        ('r', 41),
+        ('y', 97),
+    ],
+    &[
+        ('c', 325),
+        ('e', 170),
+        ('a', 15),
+        ('i', 45),
+        ('k', 99),
+        ('n', 71),
+        ('j', 106),
+        ('q', 102),
+        ('r', 43),
+        ('y', 101),
+        ('t', 105),
+        ('w', 78),
+        ('z', 108),
+    ],
+];
+
+fn letters(count: u32) -> Vec<(char, usize)> {
+    if count <= 4 {
+        HENSEL[count as usize].to_vec()
+    } else {
+        HENSEL[8 - count as usize]
+            .iter()
+            .map(|&(letter, mask)| (letter, mask ^ NEIGHBOURS))
+            .collect()
+    }
+}
+
+// The eight rotations and reflections of a neighbourhood
+fn symmetries(mask: usize) -> [usize; 8] {
+    let transform = |f: fn(i32, i32) -> (i32, i32)| {
+        (0..9)
+            .filter(|bit| mask & (1 << bit) != 0)
+            .map(|bit| {
+                let (x, y) = f(bit % 3 - 1, bit / 3 - 1);
+                1 << ((y + 1) * 3 + x + 1)
+            })
+            .fold(0, |mask, bit| mask | bit)
+    };
+    [
+        transform(|x, y| (x, y)),
+        transform(|x, y| (-y, x)),
+        transform(|x, y| (-x, -y)),
+        transform(|x, y| (y, -x)),
+        transform(|x, y| (-x, y)),
+        transform(|x, y| (x, -y)),
+        transform(|x, y| (y, x)),
+        transform(|x, y| (-y, -x)),
+    ]
+}
+
+// The Hensel letter of a neighbourhood, or None for 0 and 8 neighbours
+fn letter_of(mask: usize) -> Option<char> {
+    let neighbours = mask & NEIGHBOURS;
+    letters(neighbours.count_ones())
+        .into_iter()
+        .find(|&(_, representative)| symmetries(representative).contains(&neighbours))
+        .map(|(letter, _)| letter)
+}
+
+// Rules with more than two states are Generations rules, where a cell that
+// fails to survive decays through states 2..states before dying, and only
+// state 1 counts as a live neighbour. Golly rules instead give every state
+// its own meaning.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Isotropic(Isotropic),
+    LargerThanLife(LargerThanLife),
+    Golly(GollyRule),
+}
+
+impl Rule {
+    pub fn life() -> Self {
+        Rule::Isotropic(Isotropic::life())
+    }
+
+    pub fn states(&self) -> u32 {
+        match self {
+            Rule::Isotropic(rule) if rule.family != Family::Single => rule.family.states(),
+            Rule::Isotropic(rule) => rule.states,
+            Rule::LargerThanLife(rule) => rule.states,
+            Rule::Golly(rule) => rule.states,
+        }
+    }
+
+    // Colours the rule gives its states, as (state, rgb)
+    pub fn colours(&self) -> Vec<(u32, [u8; 3])> {
+        match self {
+            Rule::Isotropic(rule) => rule.family.colours().to_vec(),
+            Rule::LargerThanLife(_) => vec![],
+            Rule::Golly(rule) => rule.colours.clone(),
+        }
+    }
+
+    // Whether this is a Generations rule, with dying states from 2 up
+    pub fn decays(&self) -> bool {
+        match self {
+            Rule::Isotropic(rule) => rule.family == Family::Single && rule.states > 2,
+            Rule::LargerThanLife(rule) => rule.states > 2,
+            Rule::Golly(_) => false,
+        }
+    }
+
+    // Dying cells aren't alive, though every state of other rules is
+    pub fn alive(&self, state: u32) -> bool {
+        state == 1 || (state != 0 && !self.decays())
+    }
+
+    pub fn is_hexagonal(&self) -> bool {
+        matches!(
+            self,
+            Rule::Isotropic(Isotropic {
+                neighbourhood: Neighbourhood::Hexagonal,
+                ..
+            })
+        )
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(rulestring: &str) -> Result<Self, Self::Err> {
+        let rulestring = rulestring.trim();
+        // Larger than Life rules start with the range, as in R5,
+        let range = rulestring
+            .strip_prefix(['R', 'r'])
+            .and_then(|rest| rest.split_once(','))
+            .filter(|(digits, _)| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()));
+        if range.is_some() {
+            Ok(Rule::LargerThanLife(rulestring.parse()?))
+        } else {
+            Ok(Rule::Isotropic(rulestring.parse()?))
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::Isotropic(rule) => rule.fmt(f),
+            Rule::LargerThanLife(rule) => rule.fmt(f),
+            Rule::Golly(rule) => f.write_str(&rule.name),
+        }
+    }
+}
+
+impl From<GollyRule> for Rule {
+    fn from(rule: GollyRule) -> Self {
+        Rule::Golly(rule)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    Moore,
+    // Six neighbours on a skewed square grid, ignoring the north east and
+    // south west cells, as in Golly
+    Hexagonal,
+    VonNeumann,
+}
+
+impl Neighbourhood {
+    // The cells of the 3x3 block that count, including the centre
+    pub fn mask(&self) -> usize {
+        match self {
+            Neighbourhood::Moore => 0x1ff,
+            Neighbourhood::Hexagonal => 0x1bb,
+            Neighbourhood::VonNeumann => 0x0ba,
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            Neighbourhood::Moore => "",
+            Neighbourhood::Hexagonal => "H",
+            Neighbourhood::VonNeumann => "V",
+        }
+    }
+}
+
+// Multi-colour variants of a two state rule, where every live state counts as
+// alive and a cell born takes the colour held by most of its live neighbours
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Family {
+    Single,
+    // Two colours, in states 1 and 2
+    Immigration,
+    // Four colours, in states 1 to 4. A cell born from three neighbours of
+    // different colours takes the fourth.
+    QuadLife,
+}
+
+impl Family {
+    // Including the dead state
+    pub fn states(&self) -> u32 {
+        match self {
+            Family::Single => 2,
+            Family::Immigration => 3,
+            Family::QuadLife => 5,
+        }
+    }
+
+    pub fn colours(&self) -> &'static [(u32, [u8; 3])] {
+        let colours = &[
+            (1, [255, 80, 80]),
+            (2, [80, 160, 255]),
+            (3, [96, 224, 96]),
+            (4, [255, 216, 64]),
+        ];
+        match self {
+            Family::Single => &[],
+            Family::Immigration => &colours[..2],
+            Family::QuadLife => colours,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Family::Single => "",
+            Family::Immigration => "Immigration",
+            Family::QuadLife => "QuadLife",
+        }
+    }
+}
+
+// An isotropic rule within a 3x3 block. Outer totalistic rules are just the
+// special case where every letter of a count is present. Hensel letters are
+// only defined for the Moore neighbourhood.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Isotropic {
+    // Bit n is set if a cell with neighbourhood n is alive next generation
+    table: [u64; 8],
+    pub states: u32,
+    pub neighbourhood: Neighbourhood,
+    pub family: Family,
+}
+
+impl Isotropic {
+    pub fn life() -> Self {
+        "B3/S23".parse().unwrap()
+    }
+
+    pub fn next(&self, neighbourhood: usize) -> bool {
+        let neighbourhood = neighbourhood & self.neighbourhood.mask();
+        self.table[neighbourhood / 64] & (1 << (neighbourhood % 64)) != 0
+    }
+
+    // Every neighbourhood with the given centre and number of live neighbours
+    fn masks(&self, centre: usize, count: u32) -> Vec<usize> {
+        let mask = self.neighbourhood.mask();
+        (0..512)
+            .filter(|n| n & !mask == 0 && n & CENTRE == centre)
+            .filter(|n| (n & NEIGHBOURS).count_ones() == count)
+            .collect()
+    }
+
+    fn max_count(&self) -> u32 {
+        (self.neighbourhood.mask() & NEIGHBOURS).count_ones()
+    }
+
+    fn letters(&self, count: u32) -> Vec<(char, usize)> {
+        match self.neighbourhood {
+            Neighbourhood::Moore => letters(count),
+            _ => vec![],
+        }
+    }
+
+    fn set(&mut self, neighbourhood: usize) {
+        self.table[neighbourhood / 64] |= 1 << (neighbourhood % 64);
+    }
+
+    // Sets `table` for every neighbourhood with the given centre matching a
+    // section like "2-a34q", where letters select (or with '-', exclude)
+    // configurations of the preceding count
+    fn apply(&mut self, centre: usize, section: &str) -> Result<(), RuleError> {
+        let mut chars = section.chars().peekable();
+        while let Some(chr) = chars.next() {
+            let count = match chr.to_digit(10) {
+                Some(count) if count <= self.max_count() => count,
+                _ => return Err(RuleError(format!("bad neighbour count {chr:?}"))),
+            };
+            let negate = chars.next_if_eq(&'-').is_some();
+            let mut selected = vec![];
+            while let Some(letter) = chars.next_if(|chr| chr.is_ascii_lowercase()) {
+                if !self
+                    .letters(count)
+                    .iter()
+                    .any(|&(valid, _)| valid == letter)
+                {
+                    return Err(RuleError(format!("no letter {letter:?} for {count}")));
+                }
+                selected.push(letter);
+            }
+
+            for mask in self.masks(centre, count) {
+                let matches = match letter_of(mask) {
+                    Some(letter) => selected.contains(&letter),
+                    None => false,
+                };
+                if selected.is_empty() || matches != negate {
+                    self.set(mask);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn states(digits: &str) -> Result<u32, RuleError> {
+        match digits.parse::<u32>() {
+            Ok(states) if (2..=256).contains(&states) => Ok(states),
+            _ => Err(RuleError(format!("bad number of states {digits:?}"))),
+        }
+    }
+
+    // The canonical form of one half of the rule, e.g. "2-a34q"
+    fn section(&self, centre: usize) -> String {
+        let mut section = String::new();
+        for count in 0..=self.max_count() {
+            let masks = self.masks(centre, count);
+            let letters = self.letters(count);
+            let present = letters
+                .iter()
+                .filter(|&&(letter, _)| {
+                    masks
+                        .iter()
+                        .any(|&mask| self.next(mask) && letter_of(mask) == Some(letter))
+                })
+                .map(|&(letter, _)| letter)
+                .collect::<String>();
+            let absent = letters
+                .iter()
+                .map(|&(letter, _)| letter)
+                .filter(|&letter| !present.contains(letter))
+                .collect::<String>();
+
+            if masks.iter().all(|&mask| self.next(mask)) {
+                section.push_str(&count.to_string());
+            } else if !present.is_empty() {
+                section.push_str(&count.to_string());
+                if present.len() > absent.len() {
+                    section.push('-');
+                    section.push_str(&absent);
+                } else {
+                    section.push_str(&present);
+                }
+            }
+        }
+        section
+    }
+}
+
+impl FromStr for Isotropic {
+    type Err = RuleError;
+
+    // Accepts B3/S23, B2-a/S12, B2/S/C3, B2S345C4, S/B (23/3) and
+    // S/B/C (345/2/4), optionally followed by H for the hexagonal or V for
+    // the von Neumann neighbourhood. Hensel letters must be lower case.
+    // Immigration and QuadLife are Life in colour, or follow another two
+    // state rule as in B36/S23QuadLife.
+    fn from_str(rulestring: &str) -> Result<Self, Self::Err> {
+        let rulestring = rulestring.trim();
+        for family in [Family::Immigration, Family::QuadLife] {
+            let Some(rest) = rulestring.strip_suffix(family.name()) else {
+                continue;
+            };
+            let mut rule = if rest.is_empty() {
+                Isotropic::life()
+            } else {
+                rest.parse::<Isotropic>()?
+            };
+            if rule.states != 2 || rule.family != Family::Single {
+                return Err(RuleError(format!(
+                    "{} needs a two state rule",
+                    family.name()
+                )));
+            }
+            rule.family = family;
+            return Ok(rule);
+        }
+
+        let mut rule = Isotropic {
+            table: [0; 8],
+            states: 2,
+            neighbourhood: Neighbourhood::Moore,
+            family: Family::Single,
+        };
+        let rulestring = if let Some(rest) = rulestring.strip_suffix(['H', 'h']) {
+            rule.neighbourhood = Neighbourhood::Hexagonal;
+            rest
+        } else if let Some(rest) = rulestring.strip_suffix(['V', 'v']) {
+            rule.neighbourhood = Neighbourhood::VonNeumann;
+            rest
+        } else {
+            rulestring
+        };
+
+        if rulestring.starts_with(['B', 'b', 'S', 's']) {
+            // Without slashes a trailing c and digits, as in b2s345c4, is the
+            // number of states rather than a Hensel letter; B3/S2c3 keeps it
+            // as a letter
+            let rulestring = match rulestring.rsplit_once('c') {
+                Some((rest, states))
+                    if !rulestring.contains('/')
+                        && !states.is_empty()
+                        && states.bytes().all(|byte| byte.is_ascii_digit()) =>
+                {
+                    rule.states = Self::states(states)?;
+                    rest
+                }
+                _ => rulestring,
+            };
+            for part in rulestring.split('/') {
+                let mut rest = part;
+                while let Some(header) = rest.chars().next() {
+                    // Lower case b, s and g start sections too, as they
+                    // aren't Hensel letters, but c is one so only C does
+                    let end = rest[1..]
+                        .find(['B', 'S', 'C', 'G', 'b', 's', 'g'])
+                        .map_or(rest.len(), |end| end + 1);
+                    let body = &rest[1..end];
+                    match header.to_ascii_uppercase() {
+                        'B' => rule.apply(0, body)?,
+                        'S' => rule.apply(CENTRE, body)?,
+                        'C' | 'G' => rule.states = Self::states(body)?,
+                        _ => return Err(RuleError(rulestring.to_string())),
+                    }
+                    rest = &rest[end..];
+                }
+            }
+            return Ok(rule);
+        }
+
+        match rulestring.split('/').collect::<Vec<_>>()[..] {
+            [survival, birth] => {
+                rule.apply(CENTRE, survival)?;
+                rule.apply(0, birth)?;
+            }
+            [survival, birth, states] => {
+                rule.apply(CENTRE, survival)?;
+                rule.apply(0, birth)?;
+                rule.states = Self::states(states)?;
+            }
+            _ => return Err(RuleError(rulestring.to_string())),
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Isotropic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (birth, survival) = (self.section(0), self.section(CENTRE));
+        let suffix = self.neighbourhood.suffix();
+        if self.family != Family::Single {
+            let base = Isotropic {
+                family: Family::Single,
+                ..*self
+            };
+            if base == Isotropic::life() {
+                return f.write_str(self.family.name());
+            }
+            return write!(f, "{base}{}", self.family.name());
+        }
+        if self.states == 2 {
+            write!(f, "B{birth}/S{survival}{suffix}")
+        } else {
+            write!(f, "{survival}/{birth}/{}{suffix}", self.states)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Moore,
+    VonNeumann,
+    // Cells whose centres are within radius + 0.5 of this one
+    Circular,
+}
+
+// Kellie Evans' Larger than Life, in Golly's notation, e.g. Bosco's rule is
+// R5,C0,M1,S34..58,B34..45,NM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LargerThanLife {
+    pub radius: u32,
+    pub states: u32,
+    // Whether a cell counts itself as a neighbour
+    pub middle: bool,
+    pub survival: (u32, u32),
+    pub birth: (u32, u32),
+    pub shape: Shape,
+}
+
+impl LargerThanLife {
+    // The same limit as Golly
+    pub const MAX_RADIUS: u32 = 500;
+
+    fn range(value: &str) -> Result<(u32, u32), RuleError> {
+        let bad_range = || RuleError(format!("bad range {value:?}"));
+        let (min, max) = value.split_once("..").ok_or_else(bad_range)?;
+        match (min.parse(), max.parse()) {
+            (Ok(min), Ok(max)) if min <= max => Ok((min, max)),
+            _ => Err(bad_range()),
+        }
+    }
+}
+
+impl FromStr for LargerThanLife {
+    type Err = RuleError;
+
+    fn from_str(rulestring: &str) -> Result<Self, Self::Err> {
+        let mut rule = LargerThanLife {
+            radius: 1,
+            states: 2,
+            middle: false,
+            survival: (2, 3),
+            birth: (3, 3),
+            shape: Shape::Moore,
+        };
+        for part in rulestring.trim().split(',') {
+            let part = part.trim();
+            let bad_part = || RuleError(format!("bad Larger than Life section {part:?}"));
+            let mut chars = part.chars();
+            let header = chars.next().ok_or_else(bad_part)?.to_ascii_uppercase();
+            let value = chars.as_str();
+            match header {
+                'R' => match value.parse() {
+                    Ok(radius) if (1..=Self::MAX_RADIUS).contains(&radius) => rule.radius = radius,
+                    _ => return Err(bad_part()),
+                },
+                'C' => match value.parse() {
+                    Ok(0 | 1) => rule.states = 2,
+                    Ok(states) if states <= 256 => rule.states = states,
+                    _ => return Err(bad_part()),
+                },
+                'M' => match value {
+                    "0" => rule.middle = false,
+                    "1" => rule.middle = true,
+                    _ => return Err(bad_part()),
+                },
+                'S' => rule.survival = Self::range(value)?,
+                'B' => rule.birth = Self::range(value)?,
+                'N' => {
+                    rule.shape = match value.to_ascii_uppercase().as_str() {
+                        "M" => Shape::Moore,
+                        "N" => Shape::VonNeumann,
+                        "C" => Shape::Circular,
+                        _ => return Err(bad_part()),
+                    }
+                }
+                _ => return Err(bad_part()),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for LargerThanLife {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shape = match self.shape {
+            Shape::Moore => "M",
+            Shape::VonNeumann => "N",
+            Shape::Circular => "C",
+        };
+        write!(
+            f,
+            "R{},C{},M{},S{}..{},B{}..{},N{}",
+            self.radius,
+            if self.states == 2 { 0 } else { self.states },
+            self.middle as u32,
+            self.survival.0,
+            self.survival.1,
+            self.birth.0,
+            self.birth.1,
+            shape
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_every_spelling_of_life() {
+        for rulestring in ["B3/S23", "b3s23", "B3S23", "23/3", " B3/S23 "] {
+            assert_eq!(
+                rulestring.parse::<Rule>().unwrap(),
+                Rule::life(),
+                "{rulestring}"
+            );
+        }
+        assert_eq!(Rule::life().to_string(), "B3/S23");
+    }
+
+    #[test]
+    fn reads_generations_rulestrings() {
+        for rulestring in ["B2/S345/C4", "B2S345C4", "b2s345c4", "345/2/4"] {
+            let rule = rulestring.parse::<Rule>().unwrap();
+            assert_eq!(rule.to_string(), "345/2/4", "{rulestring}");
+        }
+        // With slashes a c after a count is still a Hensel letter
+        let rule = "B3/S2c3".parse::<Rule>().unwrap();
+        assert_eq!((rule.states(), rule.to_string().as_str()), (2, "B3/S2c3"));
+    }
+
+    #[test]
+    fn round_trips_hensel_notation() {
+        for rulestring in [
+            "B2-a/S12",
+            "B2n3/S23-q",
+            "B3/S2ea3eaijr4-cknqy",
+            "B2ce3ai/S",
+        ] {
+            let rule = rulestring.parse::<Rule>().unwrap();
+            assert_eq!(rule.to_string(), rulestring);
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+        }
+    }
+
+    #[test]
+    fn looks_up_hensel_letters() {
+        let Rule::Isotropic(rule) = "B2a/S".parse().unwrap() else {
+            panic!("not an isotropic rule");
+        };
+        // Two neighbours side by side along the top, then in opposite corners
+        assert!(rule.next(0b000_000_011));
+        assert!(!rule.next(0b100_000_001));
+        assert!(!rule.next(0b000_010_011));
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        for rulestring in [
+            "B9/S23",
+            "B2z/S23",
+            "B3/S23/C1",
+            "b3s23c1",
+            "R0,C0,M1,S1..2,B1..2,NM",
+        ] {
+            assert!(rulestring.parse::<Rule>().is_err(), "{rulestring}");
+        }
+    }
+
+    #[test]
+    fn only_routes_ranges_to_larger_than_life() {
+        let bosco = "R5,C0,M1,S34..58,B34..45,NM";
+        let rule = bosco.parse::<Rule>().unwrap();
+        assert!(matches!(rule, Rule::LargerThanLife(_)));
+        assert_eq!(rule.to_string(), bosco);
+        assert!(matches!(
+            "r2,c0,m0,s2..3,b3..3,nm".parse::<Rule>(),
+            Ok(Rule::LargerThanLife(_))
+        ));
+        assert!(matches!("B3/S23".parse::<Rule>(), Ok(Rule::Isotropic(_))));
+    }
+
+    #[test]
+    fn only_counts_the_first_generations_state_as_alive() {
+        let rule = "B2/S/C3".parse::<Rule>().unwrap();
+        assert_eq!(rule.states(), 3);
+        assert!(rule.decays());
+        assert!(rule.alive(1));
+        assert!(!rule.alive(2));
+        assert!(!Rule::life().decays());
+        assert!("Immigration".parse::<Rule>().unwrap().alive(2));
+    }
+}
@@ -0,0 +1,279 @@
+use rand::Rng;
+
+use crate::{
+    generate::{self, Error},
+    pattern::Pattern,
+};
+
+// A rectangle of cells on the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Selection {
+    // The rectangle with the two cells as opposite corners, cut down to fit
+    // the board, or None if it's entirely off the board
+    pub fn between(
+        a: (i32, i32),
+        b: (i32, i32),
+        board_width: u32,
+        board_height: u32,
+    ) -> Option<Self> {
+        let (min_x, max_x) = (
+            a.0.min(b.0).max(0),
+            a.0.max(b.0).min(board_width as i32 - 1),
+        );
+        let (min_y, max_y) = (
+            a.1.min(b.1).max(0),
+            a.1.max(b.1).min(board_height as i32 - 1),
+        );
+        if min_x > max_x || min_y > max_y {
+            return None;
+        }
+        Some(Self {
+            x: min_x as u32,
+            y: min_y as u32,
+            width: (max_x - min_x + 1) as u32,
+            height: (max_y - min_y + 1) as u32,
+        })
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    // Board indices of every cell inside
+    fn indices(&self, board_width: u32) -> impl Iterator<Item = usize> + '_ {
+        (self.y..self.y + self.height).flat_map(move |y| {
+            (self.x..self.x + self.width).map(move |x| (y * board_width + x) as usize)
+        })
+    }
+
+    pub fn copy(&self, data: &[u32], board_width: u32) -> Clip {
+        let mut pattern = Pattern::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = ((self.y + y) * board_width + self.x + x) as usize;
+                pattern.set(x as i32, y as i32, data[index]);
+            }
+        }
+        Clip {
+            pattern,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn clear(&self, data: &mut [u32], board_width: u32) {
+        for index in self.indices(board_width) {
+            data[index] = 0;
+        }
+    }
+
+    pub fn clear_outside(&self, data: &mut [u32], board_width: u32) {
+        for (index, state) in data.iter_mut().enumerate() {
+            let (x, y) = (index as u32 % board_width, index as u32 / board_width);
+            if !self.contains(x, y) {
+                *state = 0;
+            }
+        }
+    }
+
+    // Replaces everything inside with live cells at the given density, the
+    // same cells each time for the same seed
+    pub fn fill(
+        &self,
+        data: &mut [u32],
+        board_width: u32,
+        density: f64,
+        seed: &str,
+    ) -> Result<(), Error> {
+        generate::check_density(density)?;
+        let mut rng = generate::seeded_rng(seed);
+        for index in self.indices(board_width) {
+            data[index] = rng.gen_bool(density) as u32;
+        }
+        Ok(())
+    }
+}
+
+// Cells copied out of a selection, keeping the size of the rectangle they
+// came from so that pasting them overwrites all of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Clip {
+    pub pattern: Pattern,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Clip {
+    // A clip exactly covering a pattern
+    pub fn new(pattern: &Pattern) -> Self {
+        let pattern = pattern.normalise();
+        let (width, height) = match pattern.bounding_box() {
+            Some((_, _, max_x, max_y)) => (max_x as u32 + 1, max_y as u32 + 1),
+            None => (0, 0),
+        };
+        Self {
+            pattern,
+            width,
+            height,
+        }
+    }
+
+    pub fn rotate_clockwise(&self) -> Self {
+        Self {
+            pattern: self
+                .pattern
+                .rotate_90()
+                .translate(self.height as i32 - 1, 0),
+            width: self.height,
+            height: self.width,
+        }
+    }
+
+    pub fn rotate_anticlockwise(&self) -> Self {
+        Self {
+            pattern: self
+                .pattern
+                .rotate_270()
+                .translate(0, self.width as i32 - 1),
+            width: self.height,
+            height: self.width,
+        }
+    }
+
+    pub fn flip_horizontal(&self) -> Self {
+        Self {
+            pattern: self
+                .pattern
+                .flip_horizontal()
+                .translate(self.width as i32 - 1, 0),
+            ..self.clone()
+        }
+    }
+
+    pub fn flip_vertical(&self) -> Self {
+        Self {
+            pattern: self
+                .pattern
+                .flip_vertical()
+                .translate(0, self.height as i32 - 1),
+            ..self.clone()
+        }
+    }
+
+    // Every cell of the rectangle in reading order, including dead ones
+    pub fn states(&self) -> Vec<u32> {
+        let mut states = vec![0; (self.width * self.height) as usize];
+        for (x, y, state) in self.pattern.cells() {
+            states[(y as u32 * self.width + x as u32) as usize] = state;
+        }
+        states
+    }
+
+    // Overwrites the rectangle with its top left at (x, y), leaving off
+    // anything that falls past the edges of the board
+    pub fn paste(&self, data: &mut [u32], board_width: u32, board_height: u32, x: i32, y: i32) {
+        for (index, state) in self.states().into_iter().enumerate() {
+            let cell_x = x + (index as u32 % self.width) as i32;
+            let cell_y = y + (index as u32 / self.width) as i32;
+            if cell_x >= 0
+                && cell_y >= 0
+                && cell_x < board_width as i32
+                && cell_y < board_height as i32
+            {
+                data[(cell_y as u32 * board_width + cell_x as u32) as usize] = state;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x3 board with cells in reading order numbered from 1
+    fn board() -> Vec<u32> {
+        (1..=12).collect()
+    }
+
+    #[test]
+    fn cuts_selections_down_to_the_board() {
+        let selection = Selection::between((2, 5), (-3, 1), 4, 3).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                x: 0,
+                y: 1,
+                width: 3,
+                height: 2,
+            }
+        );
+        assert_eq!(Selection::between((4, 0), (6, 2), 4, 3), None);
+    }
+
+    #[test]
+    fn clears_inside_and_outside() {
+        let selection = Selection::between((1, 1), (2, 2), 4, 3).unwrap();
+        let mut inside = board();
+        selection.clear(&mut inside, 4);
+        assert_eq!(inside, [1, 2, 3, 4, 5, 0, 0, 8, 9, 0, 0, 12]);
+        let mut outside = board();
+        selection.clear_outside(&mut outside, 4);
+        assert_eq!(outside, [0, 0, 0, 0, 0, 6, 7, 0, 0, 10, 11, 0]);
+    }
+
+    #[test]
+    fn fills_the_same_cells_from_the_same_seed() {
+        let selection = Selection::between((0, 0), (3, 2), 4, 3).unwrap();
+        let (mut first, mut second) = (vec![0; 12], vec![0; 12]);
+        selection.fill(&mut first, 4, 0.5, "seed").unwrap();
+        selection.fill(&mut second, 4, 0.5, "seed").unwrap();
+        assert_eq!(first, second);
+        selection.fill(&mut first, 4, 1.0, "seed").unwrap();
+        assert_eq!(first, [1; 12]);
+        for density in [-0.1, 1.5, f64::NAN] {
+            assert!(matches!(
+                selection.fill(&mut first, 4, density, "seed"),
+                Err(Error::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn turns_clips_within_their_rectangle() {
+        // An L in a 2x3 rectangle
+        let clip = Clip::new(&[(0, 0), (0, 1), (0, 2), (1, 2)].into_iter().collect());
+        assert_eq!(clip.states(), [1, 0, 1, 0, 1, 1]);
+        let clockwise = clip.rotate_clockwise();
+        assert_eq!((clockwise.width, clockwise.height), (3, 2));
+        assert_eq!(clockwise.states(), [1, 1, 1, 1, 0, 0]);
+        assert_eq!(clip.rotate_anticlockwise().states(), [0, 0, 1, 1, 1, 1]);
+        assert_eq!(clip.flip_horizontal().states(), [0, 1, 0, 1, 1, 1]);
+        assert_eq!(clip.flip_vertical().states(), [1, 1, 1, 0, 1, 0]);
+        let mut turned = clip.clone();
+        for _ in 0..4 {
+            turned = turned.rotate_clockwise();
+        }
+        assert_eq!(turned, clip);
+    }
+
+    #[test]
+    fn pastes_the_whole_rectangle_inside_the_board() {
+        let selection = Selection::between((1, 0), (2, 1), 4, 3).unwrap();
+        let clip = selection.copy(&board(), 4);
+        assert_eq!(clip.states(), [2, 3, 6, 7]);
+
+        let mut data = vec![9; 12];
+        clip.paste(&mut data, 4, 3, 3, 2);
+        assert_eq!(data, [9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 2]);
+        let mut data = vec![9; 12];
+        clip.paste(&mut data, 4, 3, -1, -1);
+        assert_eq!(data, [7, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9, 9]);
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{
+    generate::Error,
+    golly,
+    palette::Palette,
+    render::{Camera, Grid},
+    rule::Rule,
+};
+
+// Everything needed to carry on a run where it was left: the board and how
+// far it's got, the rule, and how it was being looked at. Cell ages aren't
+// kept, so the age and activity views start again from the board, which
+// `Life::resume` resets them to.
+pub struct Session {
+    pub width: u32,
+    pub height: u32,
+    pub generation: u64,
+    pub rule: Rule,
+    // Whether the board is laid out as hexagons, which needn't match the rule
+    pub hexagonal: bool,
+    pub camera: Camera,
+    pub grid: Grid,
+    pub palette: Palette,
+    pub data: Vec<u32>,
+}
+
+// A gzipped header of one setting per line, then the palette in its own
+// format, then the board as little endian u32 states in reading order. A
+// Golly rule's file follows its name a line at a time.
+//
+//   size 1920 1080
+//   generation 1234
+//   rule WireWorld
+//   golly @RULE WireWorld
+//   golly ...
+//   topology square
+//   camera 960 540 0.5
+//   grid on 10
+//   palette
+//   live #ffffff
+//   ...
+//   board
+const BOARD: &[u8] = b"\nboard\n";
+
+impl Session {
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        let Camera { x, y, zoom } = self.camera;
+        writeln!(encoder, "size {} {}", self.width, self.height)?;
+        writeln!(encoder, "generation {}", self.generation)?;
+        writeln!(encoder, "rule {}", self.rule)?;
+        // Golly rules go with it, so the session can be opened anywhere
+        if let Rule::Golly(rule) = &self.rule {
+            for line in rule.source.lines() {
+                writeln!(encoder, "golly {line}")?;
+            }
+        }
+        let topology = if self.hexagonal {
+            "hexagonal"
+        } else {
+            "square"
+        };
+        writeln!(encoder, "topology {topology}")?;
+        writeln!(encoder, "camera {x} {y} {zoom}")?;
+        let visible = if self.grid.visible { "on" } else { "off" };
+        writeln!(encoder, "grid {visible} {}", self.grid.bold)?;
+        writeln!(encoder, "palette")?;
+        write!(encoder, "{}", self.palette)?;
+        encoder.write_all(&BOARD[1..])?;
+        let board: Vec<u8> = self
+            .data
+            .iter()
+            .flat_map(|state| state.to_le_bytes())
+            .collect();
+        encoder.write_all(&board)?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut bytes = vec![];
+        GzDecoder::new(BufReader::new(File::open(path)?)).read_to_end(&mut bytes)?;
+        let split = bytes
+            .windows(BOARD.len())
+            .position(|window| window == BOARD)
+            .ok_or_else(|| Error::Invalid(format!("{} has no board", path.display())))?;
+        let header = std::str::from_utf8(&bytes[..split])
+            .map_err(|_| Error::Invalid(format!("{} isn't a session", path.display())))?;
+        let (settings, palette) = header.split_once("\npalette\n").unwrap_or((header, ""));
+
+        let mut size = None;
+        let mut generation = 0;
+        let mut rule = None;
+        let mut golly = vec![];
+        let mut hexagonal = None;
+        let mut camera = None;
+        let mut grid = Grid::default();
+        for (line_number, line) in settings.lines().enumerate() {
+            let parse_error = |message: &str| Error::Parse {
+                line: line_number + 1,
+                message: message.into(),
+            };
+            if let Some(line) = line.strip_prefix("golly") {
+                golly.push(line.strip_prefix(' ').unwrap_or(line));
+                continue;
+            }
+            let (key, value) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+            let values: Vec<&str> = value.split_whitespace().collect();
+            match (key, values.as_slice()) {
+                ("size", [width, height]) => {
+                    size = Some((
+                        width.parse().map_err(|_| parse_error("invalid width"))?,
+                        height.parse().map_err(|_| parse_error("invalid height"))?,
+                    ))
+                }
+                ("generation", [value]) => {
+                    generation = value
+                        .parse()
+                        .map_err(|_| parse_error("invalid generation"))?
+                }
+                ("rule", _) => rule = Some(value),
+                ("topology", ["square"]) => hexagonal = Some(false),
+                ("topology", ["hexagonal"]) => hexagonal = Some(true),
+                ("camera", [x, y, zoom]) => {
+                    let parse = |value: &str| {
+                        value
+                            .parse::<f32>()
+                            .map_err(|_| parse_error("invalid camera"))
+                    };
+                    camera = Some(Camera {
+                        x: parse(x)?,
+                        y: parse(y)?,
+                        zoom: parse(zoom)?,
+                    });
+                }
+                ("grid", [visible @ ("on" | "off"), bold]) => {
+                    grid = Grid {
+                        visible: *visible == "on",
+                        bold: bold.parse().map_err(|_| parse_error("invalid grid"))?,
+                    }
+                }
+                ("", _) => {}
+                _ => return Err(parse_error(&format!("unexpected \"{}\"", line.trim()))),
+            }
+        }
+        let (width, height): (u32, u32) =
+            size.ok_or_else(|| Error::Invalid("the session has no size".into()))?;
+        if width == 0 || height == 0 {
+            return Err(Error::Invalid(format!(
+                "a {width}x{height} board has no cells"
+            )));
+        }
+        let rule = match rule {
+            _ if !golly.is_empty() => golly::parse(&golly.join("\n"))?.into(),
+            Some(rulestring) => rulestring
+                .parse::<Rule>()
+                .map_err(|err| Error::Invalid(err.to_string()))?,
+            None => return Err(Error::Invalid("the session has no rule".into())),
+        };
+
+        let board = &bytes[split + BOARD.len()..];
+        let cells = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|cells| cells.checked_mul(std::mem::size_of::<u32>()));
+        if cells != Some(board.len()) {
+            return Err(Error::Invalid(format!(
+                "the board isn't {width}x{height} cells"
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            generation,
+            hexagonal: hexagonal.unwrap_or(rule.is_hexagonal()),
+            rule,
+            camera: camera.unwrap_or(Camera {
+                x: width as f32 / 2.0,
+                y: height as f32 / 2.0,
+                zoom: 1.0,
+            }),
+            grid,
+            palette: palette.parse()?,
+            data: board
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // A file of its own in the temporary directory, for each test
+    fn temporary(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("libconway-{}-{name}", std::process::id()))
+    }
+
+    fn session(rule: Rule) -> Session {
+        let (width, height) = (30, 20);
+        Session {
+            width,
+            height,
+            generation: 12_345_678_901,
+            rule,
+            hexagonal: true,
+            camera: Camera {
+                x: 12.5,
+                y: -3.25,
+                zoom: 0.125,
+            },
+            grid: Grid {
+                visible: false,
+                bold: 5,
+            },
+            palette: Palette::golly(),
+            data: (0..width * height).map(|index| index % 3).collect(),
+        }
+    }
+
+    fn assert_round_trips(session: &Session, name: &str) {
+        let path = temporary(name);
+        session.write(&path).unwrap();
+        let read = Session::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((read.width, read.height), (session.width, session.height));
+        assert_eq!(read.generation, session.generation);
+        assert_eq!(read.rule, session.rule);
+        assert_eq!(read.hexagonal, session.hexagonal);
+        assert_eq!(read.camera, session.camera);
+        assert_eq!(read.grid, session.grid);
+        assert_eq!(read.palette, session.palette);
+        assert_eq!(read.data, session.data);
+    }
+
+    #[test]
+    fn round_trips_rulestrings() {
+        for rule in ["B3/S23", "B2/S34H", "R2,C0,M1,S2..3,B3..3,NM", "B2/S/C3"] {
+            assert_round_trips(&session(rule.parse().unwrap()), "rulestring.session");
+        }
+    }
+
+    #[test]
+    fn carries_golly_rules_with_it() {
+        let text = fs::read_to_string("rules/WireWorld.rule").unwrap();
+        let rule = Rule::from(golly::parse(&text).unwrap());
+        assert_round_trips(&session(rule), "golly.session");
+    }
+
+    #[test]
+    fn rejects_sizes_that_overflow() {
+        let path = temporary("overflow.session");
+        let file = File::create(&path).unwrap();
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        write!(
+            encoder,
+            "size 4294967295 4294967295\nrule B3/S23\npalette\nlive #ffffff\nboard\n"
+        )
+        .unwrap();
+        encoder.write_all(&[0; 16]).unwrap();
+        encoder.finish().unwrap();
+        let result = Session::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_empty_boards() {
+        let mut empty = session(Rule::life());
+        empty.width = 0;
+        empty.data.clear();
+        let path = temporary("empty.session");
+        empty.write(&path).unwrap();
+        let result = Session::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_files_that_arent_sessions() {
+        let path = temporary("bad.session");
+        fs::write(&path, b"nope").unwrap();
+        let result = Session::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,615 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    num::NonZeroU32,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use egui::{
+    epaint::{ImageDelta, Primitive, Vertex},
+    ClippedPrimitive, Context, ImageData, TextureFilter, TextureId, TexturesDelta,
+};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendFactor, BlendOperation, BlendState, Buffer, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, CommandEncoder, Device, Extent3d, FilterMode, FragmentState,
+    ImageCopyTexture, ImageDataLayout, IndexFormat, LoadOp, MultisampleState, Operations, Origin3d,
+    PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, SamplerBindingType,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexBufferLayout,
+    VertexFormat, VertexState, VertexStepMode,
+};
+use winit::{event::WindowEvent, window::Window};
+
+use crate::palette::Palette;
+
+// Draws egui's output with this crate's version of wgpu, over whatever's
+// already in the target
+pub struct Painter {
+    pipeline: RenderPipeline,
+    screen_buffer: Buffer,
+    screen_bind_group: BindGroup,
+    texture_bind_group_layout: BindGroupLayout,
+    textures: HashMap<TextureId, (Texture, BindGroup)>,
+}
+
+impl Painter {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("UI shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("../shaders/ui.wgsl"))),
+        });
+        let screen_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("UI screen buffer"),
+            contents: bytemuck::cast_slice(&[0f32; 4]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let screen_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("UI screen bind group layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let screen_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("UI screen bind group"),
+            layout: &screen_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            }],
+        });
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("UI texture bind group layout"),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("UI pipeline layout"),
+            bind_group_layouts: &[&screen_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("UI pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as u64,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &[
+                        VertexAttribute {
+                            offset: 0,
+                            shader_location: 0,
+                            format: VertexFormat::Float32x2,
+                        },
+                        VertexAttribute {
+                            offset: 8,
+                            shader_location: 1,
+                            format: VertexFormat::Float32x2,
+                        },
+                        VertexAttribute {
+                            offset: 16,
+                            shader_location: 2,
+                            format: VertexFormat::Uint32,
+                        },
+                    ],
+                }],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: if format.describe().srgb {
+                    "fs_linear"
+                } else {
+                    "fs_srgb"
+                },
+                targets: &[Some(ColorTargetState {
+                    format,
+                    // Premultiplied alpha
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::One,
+                            dst_factor: BlendFactor::OneMinusSrcAlpha,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::OneMinusDstAlpha,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            screen_buffer,
+            screen_bind_group,
+            texture_bind_group_layout,
+            textures: HashMap::new(),
+        }
+    }
+
+    // Draws a frame of egui's output over a target of the given size in
+    // pixels, for the caller to submit
+    #[allow(clippy::too_many_arguments)]
+    pub fn paint(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        size: [u32; 2],
+        pixels_per_point: f32,
+        primitives: &[ClippedPrimitive],
+        textures: &TexturesDelta,
+    ) {
+        for (id, delta) in &textures.set {
+            self.set_texture(device, queue, *id, delta);
+        }
+        let screen = [
+            size[0] as f32 / pixels_per_point,
+            size[1] as f32 / pixels_per_point,
+            0.0,
+            0.0,
+        ];
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&screen));
+
+        // Every mesh goes in the same pair of buffers
+        let mut vertices: Vec<Vertex> = vec![];
+        let mut indices: Vec<u32> = vec![];
+        let mut draws: Vec<(Range<u32>, i32, [u32; 4], TextureId)> = vec![];
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in primitives
+        {
+            let Primitive::Mesh(mesh) = primitive else {
+                continue;
+            };
+            // The clip rectangle in whole pixels, cut down to the target
+            let min_x = (clip_rect.min.x * pixels_per_point).round().max(0.0) as u32;
+            let min_y = (clip_rect.min.y * pixels_per_point).round().max(0.0) as u32;
+            let max_x = ((clip_rect.max.x * pixels_per_point).round() as u32).min(size[0]);
+            let max_y = ((clip_rect.max.y * pixels_per_point).round() as u32).min(size[1]);
+            if mesh.indices.is_empty() || min_x >= max_x || min_y >= max_y {
+                continue;
+            }
+            let start = indices.len() as u32;
+            draws.push((
+                start..start + mesh.indices.len() as u32,
+                vertices.len() as i32,
+                [min_x, min_y, max_x - min_x, max_y - min_y],
+                mesh.texture_id,
+            ));
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        if !draws.is_empty() {
+            let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("UI vertex buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: BufferUsages::VERTEX,
+            });
+            let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("UI index buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: BufferUsages::INDEX,
+            });
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("UI render pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint32);
+            for (range, base_vertex, [x, y, width, height], texture) in draws {
+                let Some((_, bind_group)) = self.textures.get(&texture) else {
+                    continue;
+                };
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(1, bind_group, &[]);
+                render_pass.draw_indexed(range, base_vertex, 0..1);
+            }
+        }
+
+        for id in &textures.free {
+            self.textures.remove(id);
+        }
+    }
+
+    fn set_texture(&mut self, device: &Device, queue: &Queue, id: TextureId, delta: &ImageDelta) {
+        let (width, height) = (delta.image.width() as u32, delta.image.height() as u32);
+        let pixels: Vec<u8> = match &delta.image {
+            ImageData::Color(image) => image
+                .pixels
+                .iter()
+                .flat_map(|colour| colour.to_array())
+                .collect(),
+            ImageData::Font(image) => image
+                .srgba_pixels(None)
+                .flat_map(|colour| colour.to_array())
+                .collect(),
+        };
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        // A patch of an existing texture, or a whole new one
+        let origin = match delta.pos {
+            Some([x, y]) => Origin3d {
+                x: x as u32,
+                y: y as u32,
+                z: 0,
+            },
+            None => {
+                let texture = device.create_texture(&TextureDescriptor {
+                    label: Some("UI texture"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::Rgba8UnormSrgb,
+                    usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let filter = |filter| match filter {
+                    TextureFilter::Nearest => FilterMode::Nearest,
+                    TextureFilter::Linear => FilterMode::Linear,
+                };
+                let sampler = device.create_sampler(&SamplerDescriptor {
+                    label: Some("UI sampler"),
+                    address_mode_u: AddressMode::ClampToEdge,
+                    address_mode_v: AddressMode::ClampToEdge,
+                    mag_filter: filter(delta.options.magnification),
+                    min_filter: filter(delta.options.minification),
+                    ..Default::default()
+                });
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("UI texture bind group"),
+                    layout: &self.texture_bind_group_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(
+                                &texture.create_view(&TextureViewDescriptor::default()),
+                            ),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                });
+                self.textures.insert(id, (texture, bind_group));
+                Origin3d::ZERO
+            }
+        };
+        let Some((texture, _)) = self.textures.get(&id) else {
+            return;
+        };
+        queue.write_texture(
+            ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin,
+                aspect: TextureAspect::All,
+            },
+            &pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+    }
+}
+
+// What the panel shows, filled in by whoever's running the simulation
+pub struct Status<'a> {
+    pub generation: u64,
+    // Left out when it'd be too slow to count every frame
+    pub population: Option<usize>,
+    pub rule: String,
+    pub width: u32,
+    pub height: u32,
+    pub running: bool,
+    // Generations stepped each frame
+    pub speed: u32,
+    pub hexagonal: bool,
+    pub palette: &'a Palette,
+    // How long the last step and frame took, in milliseconds
+    pub update_time: f32,
+    pub render_time: f32,
+}
+
+// Something asked for from the panel, for whoever's running the simulation
+// to carry out
+pub enum Action {
+    SetRunning(bool),
+    Step,
+    Undo,
+    Redo,
+    SetSpeed(u32),
+    SetRule(String),
+    SetHexagonal(bool),
+    SetPalette(Palette),
+    // A pattern file, either on its own or added to the board
+    Load { path: PathBuf, add: bool },
+    OpenSession(PathBuf),
+    SaveSession(PathBuf),
+}
+
+// The files it can load, including Golly rules, which switch the rule
+const PATTERN_EXTENSIONS: [&str; 6] = ["cells", "rle", "lif", "life", "mc", "rule"];
+
+// A window of controls for running experiments without the keyboard
+// shortcuts, listing the pattern files in a folder to load from
+pub struct Panel {
+    pub visible: bool,
+    rule: String,
+    path: String,
+    patterns: Vec<PathBuf>,
+    add: bool,
+}
+
+impl Panel {
+    pub fn new(library: impl AsRef<Path>, session: impl AsRef<Path>) -> Self {
+        let mut patterns: Vec<PathBuf> = fs::read_dir(library)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .map(str::to_ascii_lowercase)
+                    .is_some_and(|extension| PATTERN_EXTENSIONS.contains(&extension.as_str()))
+            })
+            .collect();
+        patterns.sort();
+        Self {
+            visible: false,
+            rule: String::new(),
+            path: session.as_ref().display().to_string(),
+            patterns,
+            add: false,
+        }
+    }
+
+    pub fn show(&mut self, context: &Context, status: &Status) -> Vec<Action> {
+        let mut actions = vec![];
+        if !self.visible {
+            return actions;
+        }
+        if self.rule.is_empty() {
+            self.rule = status.rule.clone();
+        }
+        egui::Window::new("Life")
+            .default_pos([12.0, 12.0])
+            .resizable(false)
+            .show(context, |ui| {
+                ui.label(format!("Generation {}", status.generation));
+                if let Some(population) = status.population {
+                    ui.label(format!("Population {population}"));
+                }
+                ui.label(format!(
+                    "{}x{} board, step {:.1}ms, frame {:.1}ms",
+                    status.width, status.height, status.update_time, status.render_time
+                ));
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let label = if status.running { "Pause" } else { "Run" };
+                    if ui.button(label).clicked() {
+                        actions.push(Action::SetRunning(!status.running));
+                    }
+                    if ui.button("Step").clicked() {
+                        actions.push(Action::Step);
+                    }
+                    if ui.button("Undo").clicked() {
+                        actions.push(Action::Undo);
+                    }
+                    if ui.button("Redo").clicked() {
+                        actions.push(Action::Redo);
+                    }
+                });
+                let mut speed = status.speed;
+                ui.add(egui::Slider::new(&mut speed, 1..=64).text("generations per frame"));
+                if speed != status.speed {
+                    actions.push(Action::SetSpeed(speed));
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Rule");
+                    let edit = ui.text_edit_singleline(&mut self.rule);
+                    let entered =
+                        edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Apply").clicked() || entered {
+                        actions.push(Action::SetRule(self.rule.trim().to_string()));
+                    }
+                });
+                let mut hexagonal = status.hexagonal;
+                if ui.checkbox(&mut hexagonal, "Hexagonal").changed() {
+                    actions.push(Action::SetHexagonal(hexagonal));
+                }
+                let presets = Palette::presets();
+                let current = presets
+                    .iter()
+                    .find(|(_, palette)| palette == status.palette)
+                    .map_or("custom", |(name, _)| name);
+                egui::ComboBox::from_label("Palette")
+                    .selected_text(current)
+                    .show_ui(ui, |ui| {
+                        for (name, palette) in presets.iter() {
+                            if ui.selectable_label(*name == current, *name).clicked() {
+                                actions.push(Action::SetPalette(palette.clone()));
+                            }
+                        }
+                    });
+
+                ui.separator();
+                egui::CollapsingHeader::new("Patterns").show(ui, |ui| {
+                    ui.checkbox(&mut self.add, "Add to the board");
+                    egui::ScrollArea::vertical()
+                        .max_height(160.0)
+                        .show(ui, |ui| {
+                            for path in &self.patterns {
+                                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                                if ui.button(name).clicked() {
+                                    actions.push(Action::Load {
+                                        path: path.clone(),
+                                        add: self.add,
+                                    });
+                                }
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.path);
+                    let path = PathBuf::from(self.path.trim());
+                    if ui.button("Open").clicked() {
+                        let pattern = path
+                            .extension()
+                            .and_then(|extension| extension.to_str())
+                            .map(str::to_ascii_lowercase)
+                            .is_some_and(|extension| {
+                                PATTERN_EXTENSIONS.contains(&extension.as_str())
+                            });
+                        actions.push(if pattern {
+                            Action::Load {
+                                path,
+                                add: self.add,
+                            }
+                        } else {
+                            Action::OpenSession(path)
+                        });
+                    } else if ui.button("Save").clicked() {
+                        actions.push(Action::SaveSession(path));
+                    }
+                });
+            });
+        actions
+    }
+
+    // Shows the rule as it now is, after changing it from elsewhere
+    pub fn reset_rule(&mut self) {
+        self.rule.clear();
+    }
+}
+
+// The panel with egui's state and a painter, for a winit window
+pub struct Overlay {
+    pub panel: Panel,
+    context: Context,
+    state: egui_winit::State,
+    painter: Painter,
+    output: Option<(Vec<ClippedPrimitive>, TexturesDelta)>,
+}
+
+impl Overlay {
+    pub fn new(device: &Device, format: TextureFormat, window: &Window, panel: Panel) -> Self {
+        let mut state = egui_winit::State::new_with_wayland_display(None);
+        state.set_pixels_per_point(egui_winit::native_pixels_per_point(window));
+        state.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+        Self {
+            panel,
+            context: Context::default(),
+            state,
+            painter: Painter::new(device, format),
+            output: None,
+        }
+    }
+
+    // Whether the panel took the event, so nothing else should
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        let response = self.state.on_event(&self.context, event);
+        self.panel.visible && response.consumed
+    }
+
+    // Lays out the panel for the next frame
+    pub fn update(&mut self, window: &Window, status: &Status) -> Vec<Action> {
+        let input = self.state.take_egui_input(window);
+        let mut actions = vec![];
+        let output = self.context.run(input, |context| {
+            actions = self.panel.show(context, status);
+        });
+        self.state
+            .handle_platform_output(window, &self.context, output.platform_output);
+        let primitives = self.context.tessellate(output.shapes);
+        self.output = Some((primitives, output.textures_delta));
+        actions
+    }
+
+    pub fn paint(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        size: [u32; 2],
+    ) {
+        if let Some((primitives, textures)) = self.output.take() {
+            let pixels_per_point = self.context.pixels_per_point();
+            self.painter.paint(
+                device,
+                queue,
+                encoder,
+                view,
+                size,
+                pixels_per_point,
+                &primitives,
+                &textures,
+            );
+        }
+    }
+}
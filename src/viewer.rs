@@ -0,0 +1,635 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use wgpu::{
+    CommandEncoder, Device, Instance, Queue, Surface, SurfaceConfiguration, SurfaceError,
+    TextureFormat, TextureView,
+};
+
+use crate::{
+    clipboard::SystemClipboard,
+    generate::Error,
+    golly,
+    life::{self, Config, Life},
+    load,
+    palette::Palette,
+    render::Renderer,
+    rule::Rule,
+    selection::{Clip, Selection},
+    session::Session,
+};
+
+// The simulation drawn to a surface, with everything that can be done to it
+// from a window: loading, editing, selecting, copying and pasting, moving
+// through time and saving sessions. Knows nothing about where its input comes
+// from, so any windowing library can drive it.
+pub struct Viewer {
+    surface: Surface,
+    surface_config: SurfaceConfiguration,
+    device: Device,
+    queue: Queue,
+    life: Life,
+    renderer: Renderer,
+    config: Config,
+    // Where the cursor is on the surface, in pixels
+    cursor: [f32; 2],
+    // Whether the view is being dragged around
+    panning: bool,
+    selection: Option<Selection>,
+    // The cell a selection is being dragged out from
+    anchor: Option<(i32, i32)>,
+    clipboard: Option<Clip>,
+    // Made when first needed, since there may not be one to connect to
+    system_clipboard: Option<SystemClipboard>,
+    // Whether the clipboard is following the cursor, waiting to be placed
+    pasting: bool,
+    // How full random fills of the selection are
+    fill_density: f64,
+    running: bool,
+    // Where the session is saved to and loaded from, and whether to save it
+    // on closing
+    session: PathBuf,
+    autosave: bool,
+    // Generations stepped each frame
+    speed: u32,
+    // How long the last step and frame took, in milliseconds
+    update_time: f32,
+    render_time: f32,
+}
+
+// The simulation and the renderer drawing it, which share the texture the
+// simulation writes
+async fn create_board(
+    device: &Device,
+    queue: &Queue,
+    surface_config: &SurfaceConfiguration,
+    config: &Config,
+    data: Vec<u32>,
+) -> (Life, Renderer) {
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        format: wgpu::TextureFormat::Rgba32Float,
+        view_formats: &[],
+    });
+    let output_texture_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let life = Life::new(device, &output_texture_view, config, data).await;
+
+    let mut renderer = Renderer::new(device, surface_config, &output_texture);
+    renderer.set_hexagonal(queue, config.rule.is_hexagonal());
+    renderer.set_colours(queue, &config.rule.colours());
+    renderer.fit(queue, config.width, config.height);
+    (life, renderer)
+}
+
+impl Viewer {
+    // Sets up the GPU to draw to a surface made from the instance, width by
+    // height pixels, and starts running the board
+    pub async fn new(
+        instance: &Instance,
+        surface: Surface,
+        width: u32,
+        height: u32,
+        config: Config,
+        data: Vec<u32>,
+    ) -> Self {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: Some(&surface),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let surface_caps = surface.get_capabilities(&adapter);
+        let surface_format = surface_caps
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.describe().srgb)
+            .unwrap_or(surface_caps.formats[0]);
+        let surface_config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width,
+            height,
+            present_mode: surface_caps.present_modes[0],
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: vec![],
+        };
+        surface.configure(&device, &surface_config);
+
+        let (life, renderer) = create_board(&device, &queue, &surface_config, &config, data).await;
+
+        let mut viewer = Self {
+            surface,
+            surface_config,
+            device,
+            queue,
+            life,
+            renderer,
+            config,
+            cursor: [0.0, 0.0],
+            panning: false,
+            selection: None,
+            anchor: None,
+            clipboard: None,
+            system_clipboard: None,
+            pasting: false,
+            fill_density: 0.5,
+            running: false,
+            session: PathBuf::from("life.session"),
+            autosave: false,
+            speed: 1,
+            update_time: 0.0,
+            render_time: 0.0,
+        };
+        viewer.set_running(true);
+        viewer
+    }
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+    pub fn surface_format(&self) -> TextureFormat {
+        self.surface_config.format
+    }
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+    pub fn life(&self) -> &Life {
+        &self.life
+    }
+    pub fn renderer(&self) -> &Renderer {
+        &self.renderer
+    }
+    pub fn running(&self) -> bool {
+        self.running
+    }
+    // Each run can be undone as a whole, back to where it started
+    pub fn set_running(&mut self, running: bool) {
+        if running && !self.running {
+            pollster::block_on(self.life.checkpoint(&self.device, &self.queue));
+        }
+        self.running = running;
+    }
+    pub fn update(&mut self) {
+        if !self.running {
+            return;
+        }
+        let start = Instant::now();
+        for _ in 0..self.speed {
+            pollster::block_on(self.life.step(&self.device, &self.queue));
+        }
+        self.update_time = start.elapsed().as_micros() as f32 / 1000.0;
+    }
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed.max(1);
+    }
+    // How long the last step and frame took, in milliseconds
+    pub fn update_time(&self) -> f32 {
+        self.update_time
+    }
+    pub fn render_time(&self) -> f32 {
+        self.render_time
+    }
+    pub fn render(&mut self) -> Result<(), SurfaceError> {
+        let start = Instant::now();
+        let res = self
+            .renderer
+            .render(&self.surface, &self.device, &self.queue);
+        self.render_time = start.elapsed().as_micros() as f32 / 1000.0;
+        res
+    }
+    // Draws the board, then lets overlay draw over it at the surface's size
+    // in pixels before presenting
+    pub fn render_with(
+        &mut self,
+        overlay: impl FnOnce(&Device, &Queue, &mut CommandEncoder, &TextureView, [u32; 2]),
+    ) -> Result<(), SurfaceError> {
+        let start = Instant::now();
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render encoder"),
+            });
+        self.renderer.encode(&mut encoder, &view);
+        let size = [self.surface_config.width, self.surface_config.height];
+        overlay(&self.device, &self.queue, &mut encoder, &view, size);
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+        self.render_time = start.elapsed().as_micros() as f32 / 1000.0;
+        Ok(())
+    }
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.surface_config.width = width;
+            self.surface_config.height = height;
+            self.surface.configure(&self.device, &self.surface_config);
+            self.renderer.resize(&self.queue, width, height);
+        }
+    }
+    // Starts again with a new board, which may have a different size or rule,
+    // keeping how it's drawn and what can be undone
+    fn replace_board(&mut self, config: Config, data: Vec<u32>) {
+        let (mut life, mut renderer) = pollster::block_on(create_board(
+            &self.device,
+            &self.queue,
+            &self.surface_config,
+            &config,
+            data,
+        ));
+        let old = &self.renderer;
+        renderer.set_palette(&self.queue, old.palette());
+        renderer.set_mode(&self.queue, old.mode());
+        renderer.set_ramp(&self.queue, old.ramp());
+        renderer.set_grid(&self.queue, old.grid());
+        renderer.set_smoothing(&self.queue, old.smoothing());
+        life.inherit_history(&mut self.life);
+        self.life = life;
+        self.renderer = renderer;
+        self.config = config;
+        if self.running {
+            self.running = false;
+            self.set_running(true);
+        }
+        self.set_selection(None);
+        self.set_pasting(false);
+    }
+    // Switches to another rule, keeping the board as it is
+    pub fn set_rule(&mut self, rule: Rule) {
+        if rule != self.config.rule {
+            let data = pollster::block_on(self.life.read(&self.device, &self.queue));
+            let config = Config {
+                rule,
+                ..self.config.clone()
+            };
+            self.replace_board(config, data);
+        }
+    }
+    // Puts a pattern file in the middle of the board, either on its own with
+    // the file's rule or on top of what's there, growing the board to fit.
+    // Golly .rule files just switch the rule.
+    pub fn load(&mut self, path: &Path, add: bool) -> Result<(), Error> {
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("rule"))
+        {
+            self.set_rule(golly::read(path)?.into());
+            return Ok(());
+        }
+        let file = load::read(path)?;
+        let old = add.then(|| pollster::block_on(self.life.read(&self.device, &self.queue)));
+        let limit = self.device.limits().max_texture_dimension_2d;
+        let (config, data) = load::place(&file, &self.config, old.as_deref(), limit)?;
+
+        let resized = (config.width, config.height) != (self.config.width, self.config.height);
+        pollster::block_on(self.life.checkpoint(&self.device, &self.queue));
+        if resized || config.rule != self.config.rule {
+            self.replace_board(config, data);
+        } else {
+            self.life.write(&self.device, &self.queue, &data);
+        }
+        Ok(())
+    }
+    pub fn session(&self) -> &Path {
+        &self.session
+    }
+    // Saves to and loads from somewhere else from now on
+    pub fn set_session(&mut self, path: PathBuf) {
+        self.session = path;
+    }
+    // Carries on from the session at path if there is one, and saves back to
+    // it on closing
+    pub fn open_session(&mut self, path: PathBuf) -> Result<(), Error> {
+        self.session = path;
+        self.autosave = true;
+        match self.session.exists() {
+            true => self.load_session(),
+            false => Ok(()),
+        }
+    }
+    pub fn save_session(&self) -> Result<(), Error> {
+        let renderer = &self.renderer;
+        Session {
+            width: self.config.width,
+            height: self.config.height,
+            generation: self.life.generation(),
+            rule: self.config.rule.clone(),
+            hexagonal: renderer.hexagonal(),
+            camera: renderer.camera(),
+            grid: renderer.grid(),
+            palette: renderer.palette().clone(),
+            data: pollster::block_on(self.life.read(&self.device, &self.queue)),
+        }
+        .write(&self.session)
+    }
+    // Picks up a saved session where it left off, paused
+    pub fn load_session(&mut self) -> Result<(), Error> {
+        let session = Session::read(&self.session)?;
+        let limit = self.device.limits().max_texture_dimension_2d;
+        if session.width > limit || session.height > limit {
+            return Err(Error::Invalid(format!(
+                "a {}x{} board is too big for this GPU",
+                session.width, session.height
+            )));
+        }
+        let config = Config {
+            width: session.width,
+            height: session.height,
+            rule: session.rule,
+            ..self.config.clone()
+        };
+        self.running = false;
+        self.replace_board(config, session.data.clone());
+        self.life
+            .resume(&self.device, &self.queue, session.generation, &session.data);
+        self.renderer.set_hexagonal(&self.queue, session.hexagonal);
+        self.renderer.set_camera(&self.queue, session.camera);
+        self.renderer.set_grid(&self.queue, session.grid);
+        self.renderer.set_palette(&self.queue, &session.palette);
+        Ok(())
+    }
+    // Saves the session if it was opened to be carried on with
+    pub fn close(&self) -> Result<(), Error> {
+        match self.autosave {
+            true => self.save_session(),
+            false => Ok(()),
+        }
+    }
+    fn cursor_cell(&self) -> (i32, i32) {
+        self.renderer.cell_at(self.cursor)
+    }
+    // Drags out the selection, the clipboard being pasted or the view,
+    // depending on what's going on
+    pub fn move_cursor(&mut self, position: [f32; 2]) {
+        if self.panning {
+            let mut camera = self.renderer.camera();
+            camera.pan(position[0] - self.cursor[0], position[1] - self.cursor[1]);
+            self.renderer.set_camera(&self.queue, camera);
+        }
+        self.cursor = position;
+        let (x, y) = self.cursor_cell();
+        if let Some(anchor) = self.anchor {
+            let (width, height) = (self.config.width, self.config.height);
+            self.set_selection(Selection::between(anchor, (x, y), width, height));
+        }
+        if self.pasting {
+            self.renderer.move_paste(&self.queue, x, y);
+        }
+    }
+    pub fn set_panning(&mut self, panning: bool) {
+        self.panning = panning;
+    }
+    // Zooms in or out around the cursor
+    pub fn zoom(&mut self, steps: f32) {
+        let mut camera = self.renderer.camera();
+        camera.zoom_at(1.25f32.powf(steps), self.cursor, self.renderer.viewport());
+        self.renderer.set_camera(&self.queue, camera);
+    }
+    // Places the clipboard being pasted, or starts a selection at the cursor
+    pub fn press(&mut self) {
+        let (width, height) = (self.config.width, self.config.height);
+        let (x, y) = self.cursor_cell();
+        if self.pasting {
+            let clip = self.clipboard.clone().unwrap();
+            self.edit(|data| clip.paste(data, width, height, x, y));
+            self.set_pasting(false);
+        } else {
+            self.anchor = Some((x, y));
+            self.set_selection(Selection::between((x, y), (x, y), width, height));
+        }
+    }
+    pub fn release(&mut self) {
+        // Clicking without dragging deselects
+        if self.anchor.take() == Some(self.cursor_cell()) {
+            self.set_selection(None);
+        }
+    }
+    // Reads the board back from the GPU, changes it and writes it back
+    fn edit(&mut self, edit: impl FnOnce(&mut Vec<u32>)) {
+        let mut data = pollster::block_on(self.life.read(&self.device, &self.queue));
+        self.life.record(&data);
+        edit(&mut data);
+        self.life.write(&self.device, &self.queue, &data);
+    }
+    fn set_selection(&mut self, selection: Option<Selection>) {
+        self.selection = selection;
+        self.renderer.set_selection(&self.queue, selection);
+    }
+    pub fn pasting(&self) -> bool {
+        self.pasting
+    }
+    // Shows the clipboard following the cursor until it's placed or cancelled
+    pub fn set_pasting(&mut self, pasting: bool) {
+        self.pasting = pasting && self.clipboard.is_some();
+        let clip = self.clipboard.as_ref().filter(|_| self.pasting);
+        self.renderer.set_paste(&self.device, &self.queue, clip);
+        let (x, y) = self.cursor_cell();
+        self.renderer.move_paste(&self.queue, x, y);
+    }
+    fn system_clipboard(&mut self) -> Result<&mut SystemClipboard, Error> {
+        if self.system_clipboard.is_none() {
+            self.system_clipboard = Some(SystemClipboard::new()?);
+        }
+        Ok(self.system_clipboard.as_mut().unwrap())
+    }
+    // Copies the selection, to the system clipboard too if there is one, and
+    // clears it when cutting
+    pub fn copy(&mut self, cut: bool) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+        let width = self.config.width;
+        let data = pollster::block_on(self.life.read(&self.device, &self.queue));
+        let clip = selection.copy(&data, width);
+        let rule = self.config.rule.to_string();
+        if let Err(err) = self
+            .system_clipboard()
+            .and_then(|system| system.copy(&clip.pattern, Some(&rule)))
+        {
+            log::warn!("Couldn't copy to the system clipboard: {err}");
+        }
+        self.clipboard = Some(clip);
+        if cut {
+            self.edit(|data| selection.clear(data, width));
+        }
+    }
+    // Starts pasting, falling back on the last copy when the system clipboard
+    // has no pattern
+    pub fn paste(&mut self) {
+        if let Err(err) = self.paste_system_clipboard() {
+            log::warn!("Couldn't paste from the system clipboard: {err}");
+        }
+        self.set_pasting(true);
+    }
+    // Takes a pattern from the system clipboard, unless it's the one already
+    // copied, which keeps the empty space around it
+    fn paste_system_clipboard(&mut self) -> Result<(), Error> {
+        let pasted = self.system_clipboard()?.paste()?;
+        let pattern = pasted.pattern.normalise();
+        if let Some(rule) = &pasted.rule {
+            if rule.parse::<Rule>().ok().as_ref() != Some(&self.config.rule) {
+                log::warn!("Pasting a pattern for {rule} into {}", self.config.rule);
+            }
+        }
+        let copied = self.clipboard.as_ref().map(|clip| clip.pattern.normalise());
+        if copied.as_ref() != Some(&pattern) {
+            self.clipboard = Some(Clip::new(&pattern));
+        }
+        Ok(())
+    }
+    // Turns the clipboard before it's placed
+    pub fn transform_clipboard(&mut self, transform: impl FnOnce(&Clip) -> Clip) {
+        if let Some(clip) = &self.clipboard {
+            self.clipboard = Some(transform(clip));
+            self.set_pasting(true);
+        }
+    }
+    // Clears the selection, or everything but it
+    pub fn clear(&mut self, outside: bool) {
+        let width = self.config.width;
+        if let Some(selection) = self.selection {
+            if outside {
+                self.edit(|data| selection.clear_outside(data, width));
+            } else {
+                self.edit(|data| selection.clear(data, width));
+            }
+        }
+    }
+    // Fills the selection at random, with a new seed every time
+    pub fn fill(&mut self) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+        let (width, density) = (self.config.width, self.fill_density);
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_string();
+        let mut filled = Ok(());
+        self.edit(|data| filled = selection.fill(data, width, density, &seed));
+        if let Err(err) = filled {
+            log::warn!("Couldn't fill the selection: {err}");
+        }
+    }
+    // Changes the random fill density by steps of 10%
+    pub fn change_fill_density(&mut self, steps: f64) {
+        let percent = (self.fill_density * 10.0).round() + steps;
+        self.fill_density = percent.clamp(1.0, 10.0) / 10.0;
+        log::info!("Fill density {}%", self.fill_density * 100.0);
+    }
+    // Stops running so the restored board stays put
+    pub fn undo(&mut self, redo: bool) {
+        self.running = false;
+        let snapshot = if redo {
+            pollster::block_on(self.life.redo(&self.device, &self.queue))
+        } else {
+            pollster::block_on(self.life.undo(&self.device, &self.queue))
+        };
+        if let Some(snapshot) = snapshot {
+            // Going back across a change of size or rule needs a new board
+            if snapshot.config != self.config {
+                self.replace_board(snapshot.config.clone(), snapshot.data());
+                self.life.restore(&self.device, &self.queue, &snapshot);
+            }
+            log::info!(
+                "Generation {}, with {}KiB of history",
+                self.life.generation(),
+                self.life.history().memory() / 1024
+            );
+        }
+    }
+    // Goes back or forward to a generation, paused
+    pub fn seek(&mut self, generation: u64) {
+        self.running = false;
+        if pollster::block_on(self.life.seek(&self.device, &self.queue, generation)) {
+            log::info!("Generation {generation}");
+        } else {
+            log::warn!("Can't go back to generation {generation}");
+        }
+    }
+    // Switches between square and hexagonal cells
+    pub fn set_hexagonal(&mut self, hexagonal: bool) {
+        self.renderer.set_hexagonal(&self.queue, hexagonal);
+        self.fit();
+    }
+    // Fits the whole board in the surface
+    pub fn fit(&mut self) {
+        self.renderer
+            .fit(&self.queue, self.config.width, self.config.height);
+    }
+    pub fn toggle_grid(&mut self) {
+        let mut grid = self.renderer.grid();
+        grid.visible = !grid.visible;
+        self.renderer.set_grid(&self.queue, grid);
+    }
+    // Switches between averaging cells and picking one per pixel when zoomed
+    // out
+    pub fn toggle_smoothing(&mut self) {
+        let smoothing = !self.renderer.smoothing();
+        self.renderer.set_smoothing(&self.queue, smoothing);
+    }
+    // Cycles between colouring by state, age and activity
+    pub fn next_mode(&mut self) {
+        let mode = self.renderer.mode().next();
+        self.renderer.set_mode(&self.queue, mode);
+    }
+    // Halves or doubles the length of the colour ramp
+    pub fn scale_ramp(&mut self, longer: bool) {
+        let mut ramp = self.renderer.ramp().clone();
+        ramp.generations = match longer {
+            true => ramp.generations.saturating_mul(2),
+            false => (ramp.generations / 2).max(1),
+        };
+        self.renderer.set_ramp(&self.queue, &ramp);
+    }
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.renderer.set_palette(&self.queue, palette);
+    }
+    // Cycles through the built in themes
+    pub fn next_palette(&mut self) {
+        let presets = Palette::presets();
+        let current = presets
+            .iter()
+            .position(|(_, palette)| palette == self.renderer.palette());
+        let (name, palette) = &presets[current.map_or(0, |i| (i + 1) % presets.len())];
+        log::info!("Palette {name}");
+        self.set_palette(palette);
+    }
+    // How many cells are in each state, counting each colour separately for
+    // multi-colour rules
+    pub fn populations(&self) -> Vec<usize> {
+        let data = pollster::block_on(self.life.read(&self.device, &self.queue));
+        life::populations(&data, self.config.rule.states())
+    }
+}